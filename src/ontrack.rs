@@ -1,22 +1,66 @@
+mod cache;
+mod calendar;
+mod diagnostic;
+mod matcher;
 mod processor;
+mod report;
+mod source;
 mod table_utils;
 mod types;
+mod validate;
 
-use crate::ontrack::processor::*;
+pub use crate::ontrack::cache::*;
+pub use crate::ontrack::diagnostic::*;
+pub use crate::ontrack::processor::*;
+pub use crate::ontrack::report::*;
+pub use crate::ontrack::source::*;
 use crate::ontrack::table_utils::*;
-use crate::ontrack::types::*;
+pub use crate::ontrack::types::*;
+pub use crate::ontrack::validate::*;
 use anyhow::*;
 use chrono::prelude::*;
-use chrono::Duration;
 use log::*;
 use prettytable::{cell, format::Alignment, row, Attr, Cell, Row, Table};
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::BufReader;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
 use structopt::StructOpt;
 use toggl_rs::Toggl;
 
 const DEFAULT_INPUT_FILE: &str = "toggl-ontrack.yaml";
+const CACHE_DIR_NAME: &str = "toggl-ontrack";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<OutputFormat> {
+        match value {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => bail!("unknown output format: {}", value),
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Check the input file for problems and show the hours allocated to each day of every
+    /// period, without reading any time entries
+    Check {
+        /// Input files to check instead of the one given by --input-file
+        input_files: Vec<String>,
+    },
+}
 
 /// Keep work hours on track using Toggl data
 #[derive(Debug, StructOpt)]
@@ -24,27 +68,140 @@ const DEFAULT_INPUT_FILE: &str = "toggl-ontrack.yaml";
 pub struct Options {
     /// The Toggl API token to use for authentication (from https://track.toggl.com/profile)
     #[structopt(long, env = "TOGGL_API_TOKEN")]
-    pub api_token: String,
+    pub api_token: Option<String>,
+    /// Read time entries from a Toggl JSON or CSV export instead of the Toggl API
+    #[structopt(short = "e", long, env = "TOGGL_ONTRACK_ENTRIES_FILE")]
+    pub entries_file: Option<String>,
     /// File containing expected hours per period/client/project
     #[structopt(short = "i", long, env = "TOGGL_ONTRACK_FILE", default_value = DEFAULT_INPUT_FILE)]
     pub input_file: String,
-    /// Fail with error if there are any warnings about time entries
+    /// Fail with error if there are any warnings about the input file or time entries
     #[structopt(short = "s", long)]
     pub strict: bool,
+    /// Count time where time entries overlap only once, instead of for every time entry
+    #[structopt(short = "o", long)]
+    pub count_overlaps_once: bool,
+    /// Warn about timers that have been running for more than this many hours
+    #[structopt(long, env = "TOGGL_ONTRACK_MAX_RUNNING_HOURS", default_value = "4")]
+    pub max_running_hours: f64,
     /// Show per-period hours table in addition to totals
     #[structopt(short = "p", long)]
     pub show_periods: bool,
+    /// Show per-day hours table for the current period in addition to totals
+    #[structopt(short = "d", long)]
+    pub show_days: bool,
+    /// Show billable and non-billable actual hours for the current period in the totals table
+    #[structopt(short = "b", long)]
+    pub show_billable: bool,
+    /// Show table of time entries that are missing a client or project, or that no expected
+    /// client matched, in addition to totals
+    #[structopt(short = "u", long)]
+    pub show_unmatched: bool,
+    /// Output format (text, json, or csv of per-period hours)
+    #[structopt(short = "f", long, default_value = "text")]
+    pub format: OutputFormat,
+    /// Evaluate as of this date/time instead of now (e.g. "2021-03-11 17:00"; a date alone
+    /// means the end of that day)
+    #[structopt(long, env = "TOGGL_ONTRACK_NOW", parse(try_from_str = parse_now))]
+    pub now: Option<DateTime<Local>>,
+    /// Discard cached time entries and fetch them all from Toggl again
+    #[structopt(short = "r", long)]
+    pub refresh: bool,
     /// Log verbosity level (off, error, warn, info, debug, trace)
     #[structopt(short = "v", long, default_value = "warn")]
     pub verbosity: LevelFilter,
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+fn local_datetime(datetime: NaiveDateTime) -> Result<DateTime<Local>> {
+    Local
+        .from_local_datetime(&datetime)
+        .single()
+        .ok_or_else(|| anyhow!("ambiguous or invalid local time: {}", datetime))
+}
+
+fn parse_now(value: &str) -> Result<DateTime<Local>> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Ok(datetime.with_timezone(&Local));
+    }
+    for format in &[
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+    ] {
+        if let Ok(datetime) = NaiveDateTime::parse_from_str(value, format) {
+            return local_datetime(datetime);
+        }
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .with_context(|| format!("invalid date/time: {}", value))?;
+    local_datetime(date.and_hms(23, 59, 59))
+}
+
+#[test]
+fn test_parse_now() -> Result<()> {
+    assert_eq!(
+        parse_now("2021-03-11T17:00:00Z")?,
+        Utc.ymd(2021, 3, 11).and_hms(17, 0, 0)
+    );
+    assert_eq!(
+        parse_now("2021-03-11 17:30")?,
+        Local.ymd(2021, 3, 11).and_hms(17, 30, 0)
+    );
+    assert_eq!(
+        parse_now("2021-03-11T08:15:20")?,
+        Local.ymd(2021, 3, 11).and_hms(8, 15, 20)
+    );
+    assert_eq!(
+        parse_now("2021-03-11")?,
+        Local.ymd(2021, 3, 11).and_hms(23, 59, 59)
+    );
+    assert!(parse_now("last thursday").is_err());
+    Ok(())
 }
 
-fn load_input(input_file: &str) -> Result<Input> {
-    let file = File::open(input_file)
+/// Make a path in the input file relative to the input file's directory
+fn resolve_input_path(input_file: &str, path: &mut Option<String>) {
+    if let (Some(value), Some(input_dir)) = (path.as_mut(), Path::new(input_file).parent()) {
+        *value = input_dir
+            .join(value.as_str())
+            .to_string_lossy()
+            .into_owned();
+    }
+}
+
+fn load_input(input_file: &str, today: NaiveDate, strict: bool) -> Result<Input> {
+    let text = fs::read_to_string(input_file)
         .with_context(|| format!("could not opening input file: {}", input_file))?;
-    let reader = BufReader::new(file);
-    let input: Input = serde_yaml::from_reader(reader)
+    let mut input: Input = serde_yaml::from_str(&text)
         .with_context(|| format!("could not parse input file: {}", input_file))?;
+    let problems = validate_input(today, &input, &text)?;
+    for problem in &problems {
+        match problem.severity {
+            Severity::Warning => warn!("{}", problem.describe(input_file)),
+            Severity::Error => error!("{}", problem.describe(input_file)),
+        }
+    }
+    let error_count = problems
+        .iter()
+        .filter(|v| strict || v.severity == Severity::Error)
+        .count();
+    if error_count > 0 {
+        bail!(
+            "{} problem(s) found in input file: {}",
+            error_count,
+            input_file
+        );
+    }
+    resolve_input_path(input_file, &mut input.defaults.holiday_calendar);
+    for schedule_input in &mut input.schedules {
+        resolve_input_path(input_file, &mut schedule_input.holiday_calendar);
+    }
+    for period_input in &mut input.periods {
+        resolve_input_path(input_file, &mut period_input.holiday_calendar);
+    }
     debug!("input: {:#?}", input);
     Ok(input)
 }
@@ -52,6 +209,7 @@ fn load_input(input_file: &str) -> Result<Input> {
 fn print_total_bucket_durations_table(
     now: DateTime<Local>,
     total_bucket_durations: &HashMap<Bucket, TotalDurations>,
+    show_billable: bool,
 ) {
     let mut table = Table::new();
     table.set_format(
@@ -59,7 +217,9 @@ fn print_total_bucket_durations_table(
             .column_separator(' ')
             .build(),
     );
-    table.add_row(Row::new(vec![
+    let show_status = total_bucket_durations.values().any(|v| v.status.is_some());
+    let show_running = total_bucket_durations.values().any(|v| v.running.is_some());
+    let mut group_row = Row::new(vec![
         Cell::new(""),
         Cell::new(""),
         Cell::new(""),
@@ -72,8 +232,25 @@ fn print_total_bucket_durations_table(
             .with_style(Attr::Bold),
         Cell::new(""),
         Cell::new(""),
-    ]));
-    table.add_row(row![
+    ]);
+    if show_status {
+        group_row.add_cell(Cell::new(""));
+        group_row.add_cell(Cell::new(""));
+    }
+    if show_running {
+        group_row.add_cell(Cell::new(""));
+        group_row.add_cell(Cell::new(""));
+    }
+    if show_billable {
+        group_row.add_cell(Cell::new(""));
+        group_row.add_cell(
+            Cell::new_align("CURRENT PERIOD", Alignment::CENTER)
+                .with_hspan(2)
+                .with_style(Attr::Bold),
+        );
+    }
+    table.add_row(group_row);
+    let mut header_row = row![
         b->"CLIENT",
         b->"PROJECT",
         " ",
@@ -86,27 +263,29 @@ fn print_total_bucket_durations_table(
         br->"remain",
         " ",
         br->"AVG.R"
-    ]);
-    let mut total_durations = Durations::zero();
-    let mut max_whole_days_until_end_work = 0;
+    ];
+    if show_status {
+        header_row.add_cell(cell!(""));
+        header_row.add_cell(cell!(b->"STATUS"));
+    }
+    if show_running {
+        header_row.add_cell(cell!(""));
+        header_row.add_cell(cell!(br->"RUNNING"));
+    }
+    if show_billable {
+        header_row.add_cell(cell!(""));
+        header_row.add_cell(cell!(br->"bill"));
+        header_row.add_cell(cell!(br->"nonbill"));
+    }
+    table.add_row(header_row);
     let mut sorted_buckets: Vec<_> = total_bucket_durations.keys().collect();
-    let tomorrow_date = now.date() + Duration::days(1);
     sorted_buckets.sort();
     for bucket in sorted_buckets {
-        let Bucket { client, project } = bucket;
-        let TotalDurations {
-            end_work_date,
-            durations,
-        } = total_bucket_durations.get(bucket).unwrap();
-        let whole_days_until_end_work = (*end_work_date - tomorrow_date).num_days();
-        if project.is_none() {
-            total_durations = total_durations + *durations;
-            max_whole_days_until_end_work =
-                i64::max(max_whole_days_until_end_work, whole_days_until_end_work);
-        }
-        table.add_row(Row::new(vec![
-            Cell::new(client),                                             // CLIENT
-            Cell::new(project.as_ref().map(String::as_str).unwrap_or("")), // PROJECT
+        let total = total_bucket_durations.get(bucket).unwrap();
+        let durations = &total.durations;
+        let mut row = Row::new(vec![
+            Cell::new(bucket.client_label()),   // CLIENT
+            Cell::new(&bucket.project_label()), // PROJECT
             // duration_hours_cell(durations.expected), // EXPECTED (ALL TIME)
             // duration_hours_cell(durations.partial_expected), // EXPECTED (UP TO TODAY)
             // duration_hours_cell(durations.actual), // ACTUAL (ALL TIME)
@@ -119,13 +298,40 @@ fn print_total_bucket_durations_table(
             duration_hours_cell(durations.today_actual),     // ACTUAL (TODAY)
             color_duration_hours_cell(durations.partial_remaining()), // REMAINING (TODAY)
             Cell::new(""),
-            durations
-                .daily_average_remaining(whole_days_until_end_work)
+            total
+                .daily_average_remaining(now)
                 .map(duration_hours_cell)
                 .unwrap_or_else(|| Cell::new("(n/a)")), // AVERAGE REMAINING PER DAY
-        ]));
+        ]);
+        if show_status {
+            row.add_cell(Cell::new(""));
+            row.add_cell(Cell::new(
+                &total.status.map(|v| v.to_string()).unwrap_or_default(),
+            ));
+        }
+        if show_running {
+            row.add_cell(Cell::new(""));
+            row.add_cell(
+                total
+                    .running
+                    .map(duration_hours_cell)
+                    .unwrap_or_else(|| Cell::new("")),
+            );
+        }
+        if show_billable {
+            row.add_cell(Cell::new(""));
+            row.add_cell(duration_hours_cell(
+                durations.current_period_billable_actual,
+            ));
+            row.add_cell(duration_hours_cell(
+                durations.current_period_non_billable_actual,
+            ));
+        }
+        table.add_row(row);
     }
-    table.add_row(Row::new(vec![
+    let grand_total = calculate_grand_total(now, total_bucket_durations);
+    let total_durations = grand_total.durations;
+    let mut total_row = Row::new(vec![
         Cell::new("TOTAL:").with_style(Attr::Bold),
         Cell::new(""),
         // duration_hours_cell(total_durations.expected).with_style(Attr::Bold), // EXPECTED (ALL TIME)
@@ -150,27 +356,155 @@ fn print_total_bucket_durations_table(
         color_duration_hours_cell(total_durations.actual - total_durations.partial_expected)
             .with_style(Attr::Bold), // REMAINING (TODAY)
         Cell::new(""),
-        total_durations
-            .daily_average_remaining(max_whole_days_until_end_work)
+        grand_total
+            .daily_average_remaining(now)
             .map(|v| duration_hours_cell(v).with_style(Attr::Bold))
             .unwrap_or_else(|| Cell::new("(n/a)")), // AVERAGE REMAINING PER DAY
-    ]));
+    ]);
+    if show_status {
+        total_row.add_cell(Cell::new(""));
+        total_row.add_cell(Cell::new(""));
+    }
+    if show_running {
+        total_row.add_cell(Cell::new(""));
+        total_row.add_cell(Cell::new(""));
+    }
+    if show_billable {
+        total_row.add_cell(Cell::new(""));
+        total_row.add_cell(
+            duration_hours_cell(total_durations.current_period_billable_actual)
+                .with_style(Attr::Bold),
+        );
+        total_row.add_cell(
+            duration_hours_cell(total_durations.current_period_non_billable_actual)
+                .with_style(Attr::Bold),
+        );
+    }
+    table.add_row(total_row);
     table.printstd();
 }
 
+fn cached_time_entry_source(
+    source: Box<dyn TimeEntrySource>,
+    refresh: bool,
+    now: DateTime<Local>,
+) -> Box<dyn TimeEntrySource> {
+    match (source.cache_key(), dirs::cache_dir()) {
+        (Some(cache_key), Some(cache_dir)) => {
+            let path = cache_dir
+                .join(CACHE_DIR_NAME)
+                .join(format!("{}.json", cache_key));
+            debug!("time entry cache: {}", path.display());
+            Box::new(CachingTimeEntrySource::new(source, path, refresh, now))
+        }
+        _ => source,
+    }
+}
+
+fn time_entry_source(options: &Options, now: DateTime<Local>) -> Result<Box<dyn TimeEntrySource>> {
+    match (&options.entries_file, &options.api_token) {
+        (Some(entries_file), _) => Ok(Box::new(FileTimeEntrySource::new(entries_file))),
+        (None, Some(api_token)) => {
+            let toggl = Toggl::init(api_token).context("Could not connect to Toggl")?;
+            debug!("toggl.clients: {:#?}", toggl.clients);
+            debug!("toggl.projects: {:#?}", toggl.projects);
+            Ok(cached_time_entry_source(
+                Box::new(toggl),
+                options.refresh,
+                now,
+            ))
+        }
+        (None, None) => bail!("Either a Toggl API token or a time entries file is required"),
+    }
+}
+
+fn check_input_file(input_file: &str, now: DateTime<Local>, strict: bool) -> Result<()> {
+    let input = load_input(input_file, now.date().naive_local(), strict)?;
+    let mut processor = Processor::new(now);
+    processor
+        .initialize(input)
+        .with_context(|| format!("problem found in input file: {}", input_file))?;
+    processor.print_allocation_table();
+    Ok(())
+}
+
+fn check(options: &Options, now: DateTime<Local>, input_files: &[String]) -> Result<()> {
+    let input_files = if input_files.is_empty() {
+        vec![options.input_file.clone()]
+    } else {
+        input_files.to_vec()
+    };
+    let mut failed_count = 0;
+    for (index, input_file) in input_files.iter().enumerate() {
+        if input_files.len() > 1 {
+            if index > 0 {
+                println!();
+            }
+            println!("{}:", input_file);
+        }
+        if let Err(err) = check_input_file(input_file, now, options.strict) {
+            error!("{:#}", err);
+            failed_count += 1;
+        }
+    }
+    if failed_count > 0 {
+        bail!("{} input file(s) failed the check", failed_count);
+    }
+    Ok(())
+}
+
 pub fn run(options: Options) -> Result<()> {
-    let input = load_input(&options.input_file)?;
-    let now = Local::now();
+    let now = options.now.unwrap_or_else(Local::now);
+    if let Some(Command::Check { input_files }) = &options.command {
+        return check(&options, now, input_files);
+    }
+    let input = load_input(
+        &options.input_file,
+        now.date().naive_local(),
+        options.strict,
+    )?;
     let mut processor = Processor::new(now);
+    processor.set_count_overlaps_once(options.count_overlaps_once);
+    processor.set_long_running_threshold(Some(hours_duration(options.max_running_hours)));
     processor.initialize(input)?;
-    let toggl = Toggl::init(&options.api_token).context("Could not connect to Toggl")?;
-    debug!("toggl.clients: {:#?}", toggl.clients);
-    debug!("toggl.projects: {:#?}", toggl.projects);
-    processor.process(options.strict, &toggl)?;
-    if options.show_periods {
-        processor.print_table();
+    let source = time_entry_source(&options, now)?;
+    let diagnostics = processor.process(source.as_ref())?;
+    for diagnostic in &diagnostics {
+        if diagnostic.is_warning() {
+            warn!("{}", diagnostic);
+        } else {
+            info!("{}", diagnostic);
+        }
+    }
+    if options.strict && diagnostics.iter().any(Diagnostic::is_warning) {
+        bail!("Strict mode enabled (see warning(s) above)");
     }
     let total_bucket_durations = processor.calculate_totals();
-    print_total_bucket_durations_table(now, &total_bucket_durations);
+    match options.format {
+        OutputFormat::Text => {
+            if options.show_periods {
+                processor.print_table();
+            }
+            if options.show_days {
+                processor.print_days_table();
+            }
+            if options.show_unmatched {
+                processor.print_unmatched_table();
+            }
+            print_total_bucket_durations_table(now, &total_bucket_durations, options.show_billable);
+        }
+        OutputFormat::Json => {
+            let report = Report::new(now, &processor, &total_bucket_durations);
+            serde_json::to_writer_pretty(io::stdout(), &report)?;
+            println!();
+        }
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(io::stdout());
+            for source in processor.period_bucket_durations() {
+                writer.serialize(PeriodCsvRecord::from(source))?;
+            }
+            writer.flush()?;
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,195 @@
+use crate::ontrack::source::*;
+use crate::ontrack::types::*;
+use anyhow::*;
+use chrono::prelude::*;
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct CacheData {
+    start: DateTime<Utc>,
+    complete_until: DateTime<Utc>,
+    entries: Vec<Entry>,
+}
+
+/// Caches time entries from another source on disk.  Entries from days that
+/// are over (and which have no running entry) are kept, so subsequent runs only
+/// need to fetch entries starting from the last cached day.  Days are over as
+/// of `now`.
+pub struct CachingTimeEntrySource {
+    inner: Box<dyn TimeEntrySource>,
+    path: PathBuf,
+    refresh: bool,
+    now: DateTime<Local>,
+}
+
+impl CachingTimeEntrySource {
+    pub fn new(
+        inner: Box<dyn TimeEntrySource>,
+        path: PathBuf,
+        refresh: bool,
+        now: DateTime<Local>,
+    ) -> CachingTimeEntrySource {
+        CachingTimeEntrySource {
+            inner,
+            path,
+            refresh,
+            now,
+        }
+    }
+
+    fn load(&self) -> Option<CacheData> {
+        if self.refresh {
+            return None;
+        }
+        let file = File::open(&self.path).ok()?;
+        match serde_json::from_reader(BufReader::new(file)) {
+            Ok(cache_data) => Some(cache_data),
+            Err(err) => {
+                warn!(
+                    "ignoring invalid time entry cache: {}: {}",
+                    self.path.display(),
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    fn save(&self, cache_data: &CacheData) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = File::create(&self.path)?;
+        serde_json::to_writer(BufWriter::new(file), cache_data)?;
+        Ok(())
+    }
+}
+
+fn calculate_complete_until(
+    today: Date<Local>,
+    end: DateTime<Utc>,
+    entries: &[Entry],
+) -> DateTime<Utc> {
+    let mut result = today
+        .min(end.with_timezone(&Local).date())
+        .and_hms(0, 0, 0)
+        .with_timezone(&Utc);
+    for entry in entries {
+        if entry.stop.is_none() && entry.start < result {
+            result = entry.start;
+        }
+    }
+    result
+}
+
+impl TimeEntrySource for CachingTimeEntrySource {
+    fn fetch_time_entries(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<Entry>> {
+        let (cache_start, fetch_start, mut entries) = match self.load() {
+            Some(cache_data) if cache_data.start <= start => {
+                if end < cache_data.complete_until {
+                    debug!(
+                        "time entries fully cached until {}",
+                        cache_data.complete_until
+                    );
+                    return cache_data.entries.fetch_time_entries(start, end);
+                }
+                (
+                    cache_data.start,
+                    cache_data.complete_until,
+                    cache_data.entries,
+                )
+            }
+            _ => (start, start, Vec::new()),
+        };
+        debug!("fetching uncached time entries from {}", fetch_start);
+        entries.extend(self.inner.fetch_time_entries(fetch_start, end)?);
+        let complete_until = calculate_complete_until(self.now.date(), end, &entries);
+        let cache_data = CacheData {
+            start: cache_start,
+            complete_until,
+            entries: entries
+                .iter()
+                .filter(|v| v.start < complete_until)
+                .cloned()
+                .collect(),
+        };
+        if let Err(err) = self.save(&cache_data) {
+            warn!(
+                "could not save time entry cache: {}: {:#}",
+                self.path.display(),
+                err
+            );
+        }
+        entries.fetch_time_entries(start, end)
+    }
+}
+
+#[test]
+fn test_calculate_complete_until() {
+    let entry = |start, stop| Entry::for_test(None, None, start, stop);
+    let today = Local.ymd(2021, 3, 10);
+    assert_eq!(
+        calculate_complete_until(
+            today,
+            Local.ymd(2021, 3, 10).and_hms(12, 0, 0).with_timezone(&Utc),
+            &[entry(
+                Local.ymd(2021, 3, 9).and_hms(9, 0, 0),
+                Some(Local.ymd(2021, 3, 9).and_hms(10, 0, 0))
+            )]
+        ),
+        Local.ymd(2021, 3, 10).and_hms(0, 0, 0).with_timezone(&Utc)
+    );
+    assert_eq!(
+        calculate_complete_until(
+            today,
+            Local.ymd(2021, 3, 8).and_hms(12, 0, 0).with_timezone(&Utc),
+            &[]
+        ),
+        Local.ymd(2021, 3, 8).and_hms(0, 0, 0).with_timezone(&Utc)
+    );
+    assert_eq!(
+        calculate_complete_until(
+            today,
+            Local.ymd(2021, 3, 10).and_hms(12, 0, 0).with_timezone(&Utc),
+            &[entry(Local.ymd(2021, 3, 9).and_hms(22, 0, 0), None)]
+        ),
+        Local.ymd(2021, 3, 9).and_hms(22, 0, 0).with_timezone(&Utc)
+    );
+}
+
+#[test]
+fn test_caching_time_entry_source() -> Result<()> {
+    let entry = |day| Entry {
+        id: Some(day as i64),
+        ..Entry::for_test(
+            None,
+            None,
+            Local.ymd(2021, 3, day).and_hms(9, 0, 0),
+            Some(Local.ymd(2021, 3, day).and_hms(10, 0, 0)),
+        )
+    };
+    let path = std::env::temp_dir().join(format!(
+        "toggl-ontrack-test-cache-{}.json",
+        std::process::id()
+    ));
+    let start = Local.ymd(2021, 3, 7).and_hms(0, 0, 0).with_timezone(&Utc);
+    let end = Local.ymd(2021, 3, 10).and_hms(12, 0, 0).with_timezone(&Utc);
+    let now = Local.ymd(2021, 3, 10).and_hms(12, 0, 0);
+    let first =
+        CachingTimeEntrySource::new(Box::new(vec![entry(8), entry(10)]), path.clone(), true, now);
+    assert_eq!(
+        first.fetch_time_entries(start, end)?,
+        vec![entry(8), entry(10)]
+    );
+    let second =
+        CachingTimeEntrySource::new(Box::new(Vec::<Entry>::new()), path.clone(), false, now);
+    let second_result = second.fetch_time_entries(start, end);
+    fs::remove_file(&path)?;
+    assert_eq!(second_result?, vec![entry(8)]);
+    Ok(())
+}
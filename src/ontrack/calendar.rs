@@ -0,0 +1,299 @@
+use anyhow::*;
+use chrono::prelude::*;
+use chrono::Duration;
+use log::*;
+use std::collections::HashSet;
+use std::fs;
+
+/// Join folded iCalendar content lines (continuation lines start with
+/// whitespace)
+fn unfold_lines(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some(last) = lines.last_mut() {
+                last.push_str(&line[1..]);
+                continue;
+            }
+        }
+        lines.push(line.to_string());
+    }
+    lines
+}
+
+fn parse_duration_days(value: &str) -> Option<i64> {
+    let value = value.strip_prefix('P')?;
+    if let Some(days) = value.strip_suffix('D') {
+        days.parse().ok()
+    } else if let Some(weeks) = value.strip_suffix('W') {
+        weeks.parse::<i64>().ok().map(|v| v * 7)
+    } else {
+        None
+    }
+}
+
+/// How many years past its start a recurring event without `UNTIL` or `COUNT`
+/// is expanded
+const MAX_RECURRENCE_YEARS: i32 = 50;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Yearly,
+}
+
+/// Start dates of the occurrences of a recurring all-day event.  `FREQ` of
+/// `DAILY`, `WEEKLY` or `YEARLY`, with `INTERVAL`, `UNTIL` and `COUNT`, are
+/// supported.  For any other rule, only the first occurrence is used.
+fn recurrence_dates(start: NaiveDate, rrule: &str) -> Result<Vec<NaiveDate>> {
+    let mut frequency = None;
+    let mut interval: i64 = 1;
+    let mut until = None;
+    let mut count: Option<usize> = None;
+    for part in rrule.split(';') {
+        let (key, value) = match part.find('=') {
+            Some(separator) => (&part[..separator], &part[separator + 1..]),
+            None => (part, ""),
+        };
+        match (key.to_ascii_uppercase().as_str(), value) {
+            ("FREQ", "DAILY") => frequency = Some(Frequency::Daily),
+            ("FREQ", "WEEKLY") => frequency = Some(Frequency::Weekly),
+            ("FREQ", "YEARLY") => frequency = Some(Frequency::Yearly),
+            ("INTERVAL", _) => {
+                interval = value
+                    .parse()
+                    .ok()
+                    .filter(|v| *v > 0)
+                    .ok_or_else(|| anyhow!("invalid RRULE INTERVAL: {}", value))?;
+            }
+            ("UNTIL", _) => {
+                until = Some(
+                    NaiveDate::parse_from_str(value.get(..8).unwrap_or(value), "%Y%m%d")
+                        .with_context(|| format!("invalid RRULE UNTIL date: {}", value))?,
+                );
+            }
+            ("COUNT", _) => {
+                count = Some(
+                    value
+                        .parse()
+                        .with_context(|| format!("invalid RRULE COUNT: {}", value))?,
+                );
+            }
+            ("WKST", _) => (),
+            _ => {
+                warn!(
+                    "Unsupported recurrence rule in holiday calendar, \
+                     only using the first occurrence: {}",
+                    rrule
+                );
+                return Ok(vec![start]);
+            }
+        }
+    }
+    let frequency = match frequency {
+        Some(frequency) => frequency,
+        None => bail!("RRULE without a supported FREQ: {}", rrule),
+    };
+    let until = match (until, count) {
+        (Some(until), _) => until,
+        (None, Some(_)) => chrono::naive::MAX_DATE,
+        (None, None) => NaiveDate::from_ymd(start.year() + MAX_RECURRENCE_YEARS, 12, 31),
+    };
+    let mut result = Vec::new();
+    let mut step: i64 = 0;
+    while count.map_or(true, |v| result.len() < v) {
+        let date = match frequency {
+            Frequency::Daily | Frequency::Weekly => {
+                let days = step
+                    .saturating_mul(interval)
+                    .saturating_mul(if frequency == Frequency::Weekly { 7 } else { 1 });
+                if days > (until - start).num_days() {
+                    break;
+                }
+                Some(start + Duration::days(days))
+            }
+            Frequency::Yearly => {
+                let year = i64::from(start.year()).saturating_add(step.saturating_mul(interval));
+                if year > i64::from(until.year()) {
+                    break;
+                }
+                // Skips February 29 in years that are not leap years
+                start.with_year(year as i32)
+            }
+        };
+        if let Some(date) = date.filter(|v| *v <= until) {
+            result.push(date);
+        }
+        step += 1;
+    }
+    Ok(result)
+}
+
+#[test]
+fn test_recurrence_dates() -> Result<()> {
+    let start = NaiveDate::from_ymd(2020, 2, 29);
+    assert_eq!(
+        recurrence_dates(start, "FREQ=YEARLY;UNTIL=20280229T000000Z")?,
+        vec![
+            start,
+            NaiveDate::from_ymd(2024, 2, 29),
+            NaiveDate::from_ymd(2028, 2, 29)
+        ]
+    );
+    assert_eq!(
+        recurrence_dates(start, "FREQ=DAILY;INTERVAL=3;COUNT=2")?,
+        vec![start, NaiveDate::from_ymd(2020, 3, 3)]
+    );
+    assert_eq!(recurrence_dates(start, "FREQ=WEEKLY")?.len(), 2653);
+    assert_eq!(
+        recurrence_dates(start, "FREQ=MONTHLY;BYMONTHDAY=1")?,
+        vec![start]
+    );
+    assert!(recurrence_dates(start, "FREQ=DAILY;INTERVAL=0").is_err());
+    Ok(())
+}
+
+/// Parse the dates covered by all-day events in iCalendar text, including the
+/// occurrences of recurring events (see `recurrence_dates`) other than those
+/// excluded by `EXDATE`.  Events with a time of day are ignored.
+pub fn parse_ical_all_day_dates(text: &str) -> Result<HashSet<NaiveDate>> {
+    let mut result = HashSet::new();
+    let mut in_event = false;
+    let mut start: Option<NaiveDate> = None;
+    let mut end: Option<NaiveDate> = None;
+    let mut duration_days = None;
+    let mut rrule: Option<String> = None;
+    let mut exdates = HashSet::new();
+    for line in unfold_lines(text) {
+        let separator = match line.find(':') {
+            Some(separator) => separator,
+            None => continue,
+        };
+        let (name_params, value) = (&line[..separator], &line[separator + 1..]);
+        let mut name_params = name_params.split(';');
+        let name = name_params.next().unwrap_or("").to_ascii_uppercase();
+        let is_date =
+            name_params.any(|v| v.eq_ignore_ascii_case("VALUE=DATE")) || !value.contains('T');
+        match (name.as_str(), value) {
+            ("BEGIN", "VEVENT") => {
+                in_event = true;
+                start = None;
+                end = None;
+                duration_days = None;
+                rrule = None;
+                exdates.clear();
+            }
+            ("END", "VEVENT") => {
+                in_event = false;
+                if let Some(start) = start {
+                    let days = end
+                        .map(|v| (v - start).num_days())
+                        .or(duration_days)
+                        .unwrap_or(1);
+                    let occurrences = match &rrule {
+                        Some(rrule) => recurrence_dates(start, rrule)?,
+                        None => vec![start],
+                    };
+                    for occurrence in occurrences.into_iter().filter(|v| !exdates.contains(v)) {
+                        for offset in 0..days {
+                            result.insert(occurrence + Duration::days(offset));
+                        }
+                    }
+                }
+            }
+            ("DTSTART", _) if in_event && is_date => {
+                start = Some(
+                    NaiveDate::parse_from_str(value, "%Y%m%d")
+                        .with_context(|| format!("invalid DTSTART date: {}", value))?,
+                );
+            }
+            ("DTEND", _) if in_event && is_date => {
+                end = Some(
+                    NaiveDate::parse_from_str(value, "%Y%m%d")
+                        .with_context(|| format!("invalid DTEND date: {}", value))?,
+                );
+            }
+            ("DURATION", _) if in_event => {
+                duration_days = parse_duration_days(value);
+            }
+            ("RRULE", _) if in_event => {
+                rrule = Some(value.to_string());
+            }
+            ("EXDATE", _) if in_event => {
+                for exdate in value.split(',') {
+                    exdates.insert(
+                        NaiveDate::parse_from_str(exdate.get(..8).unwrap_or(exdate), "%Y%m%d")
+                            .with_context(|| format!("invalid EXDATE date: {}", exdate))?,
+                    );
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(result)
+}
+
+#[test]
+fn test_parse_ical_all_day_dates() -> Result<()> {
+    assert_eq!(
+        parse_ical_all_day_dates(
+            "BEGIN:VCALENDAR\r\n\
+             VERSION:2.0\r\n\
+             BEGIN:VEVENT\r\n\
+             SUMMARY:Good\r\n  Friday\r\n\
+             DTSTART;VALUE=DATE:20210402\r\n\
+             END:VEVENT\r\n\
+             BEGIN:VEVENT\r\n\
+             SUMMARY:Winter break\r\n\
+             DTSTART;VALUE=DATE:20211230\r\n\
+             DTEND;VALUE=DATE:20220102\r\n\
+             END:VEVENT\r\n\
+             BEGIN:VEVENT\r\n\
+             SUMMARY:Offsite\r\n\
+             DTSTART:20210601\r\n\
+             DURATION:P2D\r\n\
+             END:VEVENT\r\n\
+             BEGIN:VEVENT\r\n\
+             SUMMARY:New Year's Day\r\n\
+             DTSTART;VALUE=DATE:20200101\r\n\
+             RRULE:FREQ=YEARLY;UNTIL=20211231\r\n\
+             END:VEVENT\r\n\
+             BEGIN:VEVENT\r\n\
+             SUMMARY:Summer Fridays\r\n\
+             DTSTART;VALUE=DATE:20210702\r\n\
+             RRULE:FREQ=WEEKLY;INTERVAL=2;COUNT=4\r\n\
+             EXDATE;VALUE=DATE:20210716\r\n\
+             EXDATE:20210813T000000Z,20210820T000000Z\r\n\
+             END:VEVENT\r\n\
+             BEGIN:VEVENT\r\n\
+             SUMMARY:Meeting\r\n\
+             DTSTART:20210405T090000Z\r\n\
+             DTEND:20210405T100000Z\r\n\
+             END:VEVENT\r\n\
+             END:VCALENDAR\r\n"
+        )?,
+        vec![
+            NaiveDate::from_ymd(2021, 4, 2),
+            NaiveDate::from_ymd(2021, 12, 30),
+            NaiveDate::from_ymd(2021, 12, 31),
+            NaiveDate::from_ymd(2022, 1, 1),
+            NaiveDate::from_ymd(2021, 6, 1),
+            NaiveDate::from_ymd(2021, 6, 2),
+            NaiveDate::from_ymd(2020, 1, 1),
+            NaiveDate::from_ymd(2021, 1, 1),
+            NaiveDate::from_ymd(2021, 7, 2),
+            NaiveDate::from_ymd(2021, 7, 30),
+        ]
+        .into_iter()
+        .collect()
+    );
+    Ok(())
+}
+
+pub fn load_ical_holidays(path: &str) -> Result<HashSet<NaiveDate>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("could not read holiday calendar: {}", path))?;
+    parse_ical_all_day_dates(&text)
+        .with_context(|| format!("could not parse holiday calendar: {}", path))
+}
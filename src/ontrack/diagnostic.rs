@@ -0,0 +1,123 @@
+use crate::ontrack::table_utils::*;
+use chrono::prelude::*;
+use chrono::Duration;
+use std::fmt;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DiagnosticKind {
+    /// The time entry has a client and project, but no expected client matched
+    UnmatchedEntry,
+    /// The time entry has no client, and is not in any period
+    MissingClient,
+    /// The time entry has a client but no project, and no expected client
+    /// matched
+    MissingProject,
+    /// The time entry's timer is still running, so it is counted up to now
+    RunningEntry,
+    /// The time entry's timer has been running for longer than the threshold,
+    /// so it may have been left on by mistake
+    LongRunningEntry { threshold: Duration },
+    /// The time entry is not in any period
+    OutsidePeriods,
+    /// The time entry starts before an earlier starting time entry stops
+    OverlappingEntries {
+        other_entry_id: Option<i64>,
+        overlap_start: DateTime<Utc>,
+        overlap_stop: DateTime<Utc>,
+    },
+}
+
+/// Something noteworthy about a time entry found while processing it, with the
+/// part of the entry's duration it applies to
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub entry_id: Option<i64>,
+    pub start: DateTime<Utc>,
+    pub duration: Duration,
+}
+
+impl Diagnostic {
+    /// Whether this is a problem with the time entries, which fails strict
+    /// mode, rather than just information
+    pub fn is_warning(&self) -> bool {
+        match self.kind {
+            DiagnosticKind::RunningEntry => false,
+            DiagnosticKind::UnmatchedEntry
+            | DiagnosticKind::MissingClient
+            | DiagnosticKind::MissingProject
+            | DiagnosticKind::LongRunningEntry { .. }
+            | DiagnosticKind::OutsidePeriods
+            | DiagnosticKind::OverlappingEntries { .. } => true,
+        }
+    }
+}
+
+fn entry_id_label(entry_id: Option<i64>) -> String {
+    entry_id.map_or_else(|| "without id".to_string(), |v| v.to_string())
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "time entry {} starting {} ({}): ",
+            entry_id_label(self.entry_id),
+            self.start.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+            format_duration_hours(self.duration)
+        )?;
+        match &self.kind {
+            DiagnosticKind::UnmatchedEntry => write!(f, "no expected client/project matched"),
+            DiagnosticKind::MissingClient => write!(f, "missing client"),
+            DiagnosticKind::MissingProject => write!(f, "missing project"),
+            DiagnosticKind::RunningEntry => write!(f, "still running"),
+            DiagnosticKind::LongRunningEntry { threshold } => write!(
+                f,
+                "still running for more than {}",
+                format_duration_hours(*threshold)
+            ),
+            DiagnosticKind::OutsidePeriods => write!(f, "not in any period"),
+            DiagnosticKind::OverlappingEntries {
+                other_entry_id,
+                overlap_start,
+                overlap_stop,
+            } => write!(
+                f,
+                "overlaps time entry {} from {} to {}",
+                entry_id_label(*other_entry_id),
+                overlap_start.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+                overlap_stop.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+            ),
+        }
+    }
+}
+
+#[test]
+fn test_diagnostic_display() {
+    let start = Local.ymd(2021, 3, 8).and_hms(9, 0, 0).with_timezone(&Utc);
+    assert_eq!(
+        Diagnostic {
+            kind: DiagnosticKind::MissingClient,
+            entry_id: Some(123),
+            start,
+            duration: Duration::minutes(90),
+        }
+        .to_string(),
+        "time entry 123 starting 2021-03-08 09:00 (1:30): missing client"
+    );
+    assert_eq!(
+        Diagnostic {
+            kind: DiagnosticKind::OverlappingEntries {
+                other_entry_id: None,
+                overlap_start: start,
+                overlap_stop: start + Duration::minutes(5),
+            },
+            entry_id: Some(123),
+            start,
+            duration: Duration::minutes(5),
+        }
+        .to_string(),
+        "time entry 123 starting 2021-03-08 09:00 (0:05): \
+         overlaps time entry without id from 2021-03-08 09:00 to 2021-03-08 09:05"
+    );
+}
@@ -0,0 +1,193 @@
+use crate::ontrack::types::*;
+use anyhow::*;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+
+/// A client or project name from the input file.  Names starting with `glob:`
+/// are glob patterns (so existing names containing `*` or `?` still match
+/// exactly), and names surrounded by `/` are regular expressions.  Both must
+/// match the whole time entry name.
+#[derive(Clone, Debug)]
+pub enum NamePattern {
+    Exact(String),
+    Pattern(Box<Regex>),
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut result = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => result.push_str(".*"),
+            '?' => result.push('.'),
+            _ => result.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    result.push('$');
+    result
+}
+
+impl NamePattern {
+    pub fn parse(name: &str) -> Result<NamePattern> {
+        if name.len() > 1 && name.starts_with('/') && name.ends_with('/') {
+            let regex = Regex::new(&format!("^(?:{})$", &name[1..name.len() - 1]))
+                .with_context(|| format!("Invalid regular expression: {}", name))?;
+            Ok(NamePattern::Pattern(Box::new(regex)))
+        } else if let Some(glob) = name.strip_prefix("glob:") {
+            let regex = Regex::new(&glob_to_regex(glob))?;
+            Ok(NamePattern::Pattern(Box::new(regex)))
+        } else {
+            Ok(NamePattern::Exact(name.to_string()))
+        }
+    }
+
+    pub fn is_match(&self, name: &str) -> bool {
+        match self {
+            NamePattern::Exact(exact) => exact == name,
+            NamePattern::Pattern(regex) => regex.is_match(name),
+        }
+    }
+}
+
+#[test]
+fn test_name_pattern() -> Result<()> {
+    let exact = NamePattern::parse("ACME Corp")?;
+    assert!(exact.is_match("ACME Corp"));
+    assert!(!exact.is_match("ACME Corp."));
+    let exact = NamePattern::parse("ACME*")?;
+    assert!(exact.is_match("ACME*"));
+    assert!(!exact.is_match("ACME Corp"));
+    let glob = NamePattern::parse("glob:ACME*")?;
+    assert!(glob.is_match("ACME"));
+    assert!(glob.is_match("ACME Corp"));
+    assert!(!glob.is_match("Old ACME"));
+    let glob = NamePattern::parse("glob:v1.?")?;
+    assert!(glob.is_match("v1.2"));
+    assert!(!glob.is_match("v1x2"));
+    let regex = NamePattern::parse("/ACME( Corp)?/")?;
+    assert!(regex.is_match("ACME"));
+    assert!(regex.is_match("ACME Corp"));
+    assert!(!regex.is_match("ACME Corp."));
+    assert!(NamePattern::parse("/(/").is_err());
+    Ok(())
+}
+
+/// The patterns for a client or project: its name and any aliases.  Each name
+/// or alias is only added once, however many periods list it.
+#[derive(Clone, Debug, Default)]
+pub struct NameMatcher {
+    patterns: Vec<NamePattern>,
+    sources: HashSet<String>,
+}
+
+impl NameMatcher {
+    fn add(&mut self, name: &str) -> Result<()> {
+        if !self.sources.contains(name) {
+            self.patterns.push(NamePattern::parse(name)?);
+            self.sources.insert(name.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn is_match(&self, name: &str) -> bool {
+        self.patterns.iter().any(|v| v.is_match(name))
+    }
+}
+
+/// Matchers for the clients and projects in the input file, keyed by their
+/// names.  Aliases from every period are combined.
+#[derive(Clone, Debug, Default)]
+pub struct NameMatchers {
+    clients: HashMap<String, NameMatcher>,
+    projects: HashMap<(String, String), NameMatcher>,
+}
+
+fn add_name_matcher(matcher: &mut NameMatcher, name: &str, aliases: &[String]) -> Result<()> {
+    matcher.add(name)?;
+    for alias in aliases {
+        matcher.add(alias)?;
+    }
+    Ok(())
+}
+
+impl NameMatchers {
+    pub fn add_client(&mut self, client_name: &str, aliases: &[String]) -> Result<()> {
+        let matcher = self
+            .clients
+            .entry(client_name.to_string())
+            .or_insert_with(NameMatcher::default);
+        add_name_matcher(matcher, client_name, aliases)
+            .with_context(|| format!("Invalid name or alias for client: {}", client_name))
+    }
+
+    pub fn add_project(
+        &mut self,
+        client_name: &str,
+        project_name: &str,
+        aliases: &[String],
+    ) -> Result<()> {
+        let matcher = self
+            .projects
+            .entry((client_name.to_string(), project_name.to_string()))
+            .or_insert_with(NameMatcher::default);
+        add_name_matcher(matcher, project_name, aliases).with_context(|| {
+            format!(
+                "Invalid name or alias for project: {} / {}",
+                client_name, project_name
+            )
+        })
+    }
+
+    /// Whether a time entry belongs in a bucket, ignoring the bucket's period
+    pub fn bucket_matches(&self, bucket: &Bucket, time_entry: &Entry) -> bool {
+        let client_matches = match (&bucket.client, &time_entry.client) {
+            (None, _) => true,
+            (Some(client), Some(entry_client)) => self
+                .clients
+                .get(client)
+                .map_or(client == entry_client, |v| v.is_match(entry_client)),
+            (Some(_), None) => false,
+        };
+        let project_matches = match (&bucket.client, &bucket.project, &time_entry.project) {
+            (_, None, _) => true,
+            (Some(client), Some(project), Some(entry_project)) => self
+                .projects
+                .get(&(client.clone(), project.clone()))
+                .map_or(project == entry_project, |v| v.is_match(entry_project)),
+            (_, Some(_), _) => false,
+        };
+        client_matches
+            && project_matches
+            && bucket
+                .tag
+                .as_ref()
+                .map_or(true, |tag| time_entry.tags.contains(tag))
+    }
+}
+
+#[test]
+fn test_bucket_matches() -> Result<()> {
+    let mut matchers = NameMatchers::default();
+    matchers.add_client("ACME", &["glob:ACME*".to_string()])?;
+    matchers.add_project("ACME", "Website", &["/Web ?site/".to_string()])?;
+    let bucket = |project: Option<&str>| Bucket {
+        project: project.map(String::from),
+        ..client_bucket("ACME")
+    };
+    let entry =
+        |client, project| Entry::for_test(Some(client), Some(project), chrono::Local::now(), None);
+    assert!(matchers.bucket_matches(&bucket(None), &entry("ACME Corp", "Other")));
+    assert!(!matchers.bucket_matches(&bucket(None), &entry("Other", "Other")));
+    assert!(matchers.bucket_matches(&bucket(Some("Website")), &entry("ACME Corp", "Web site")));
+    assert!(!matchers.bucket_matches(&bucket(Some("Website")), &entry("ACME Corp", "Other")));
+    Ok(())
+}
+
+#[test]
+fn test_add_client_once_per_alias() -> Result<()> {
+    let mut matchers = NameMatchers::default();
+    // As for a client listed in two periods
+    matchers.add_client("ACME", &["glob:ACME*".to_string()])?;
+    matchers.add_client("ACME", &["glob:ACME*".to_string(), "Acme".to_string()])?;
+    assert_eq!(matchers.clients["ACME"].patterns.len(), 3);
+    Ok(())
+}
@@ -1,3 +1,7 @@
+use crate::ontrack::calendar::*;
+use crate::ontrack::diagnostic::*;
+use crate::ontrack::matcher::*;
+use crate::ontrack::source::*;
 use crate::ontrack::table_utils::*;
 use crate::ontrack::types::*;
 use anyhow::*;
@@ -5,11 +9,11 @@ use chrono::prelude::*;
 use chrono::Duration;
 use log::*;
 use prettytable::{cell, row, Cell, Row, Table};
-use std::collections::{HashMap, HashSet};
-use toggl_rs::{TimeEntry, Toggl, TogglExt};
+use std::cmp::Ordering;
+use std::collections::{hash_map, BTreeMap, HashMap, HashSet};
 
-const DEFAULT_PERIOD_LENGTH: i64 = 7;
-const DEFAULT_WORK_DAYS: WorkDaysInput = WorkDaysInput::FromToWeekdays {
+pub const DEFAULT_PERIOD_LENGTH: i64 = 7;
+pub const DEFAULT_WORK_DAYS: WorkDaysInput = WorkDaysInput::FromToWeekdays {
     from: Weekday::Mon,
     to: Weekday::Fri,
 };
@@ -140,10 +144,11 @@ fn test_is_date_in_period() {
     ));
 }
 
-fn preallocate_hours(
+pub(crate) fn preallocate_hours(
     period_start: Date<Local>,
     period_length: i64,
     work_days_input: &WorkDaysInput,
+    holidays: &HashSet<NaiveDate>,
 ) -> Result<(HashMap<i64, f64>, f64)> {
     let mut offsets = HashMap::new();
     let mut total = 0.0;
@@ -188,6 +193,14 @@ fn preallocate_hours(
             }
         }
     }
+    for holiday in holidays {
+        let offset = (*holiday - period_start.naive_local()).num_days();
+        if offset >= 0 && offset < period_length {
+            if let Some(hours) = offsets.insert(offset, 0.0) {
+                total -= hours;
+            }
+        }
+    }
     Ok((offsets, total))
 }
 
@@ -200,7 +213,8 @@ fn test_preallocate_hours() -> Result<()> {
             &WorkDaysInput::FromToWeekdays {
                 from: Weekday::Mon,
                 to: Weekday::Fri
-            }
+            },
+            &HashSet::new()
         )?,
         (
             vec![(0, 0.0), (6, 0.0), (7, 0.0)].into_iter().collect(),
@@ -211,14 +225,16 @@ fn test_preallocate_hours() -> Result<()> {
         preallocate_hours(
             Local.ymd(2021, 3, 14),
             7,
-            &WorkDaysInput::FromToOffsets { from: 1, to: 5 }
+            &WorkDaysInput::FromToOffsets { from: 1, to: 5 },
+            &HashSet::new()
         )?,
         (vec![(0, 0.0), (6, 0.0)].into_iter().collect(), 0.0)
     );
     assert!(preallocate_hours(
         Local.ymd(2021, 3, 14),
         7,
-        &WorkDaysInput::FromToOffsets { from: 5, to: 1 }
+        &WorkDaysInput::FromToOffsets { from: 5, to: 1 },
+        &HashSet::new()
     )
     .is_err());
     assert_eq!(
@@ -232,47 +248,303 @@ fn test_preallocate_hours() -> Result<()> {
                 ]
                 .into_iter()
                 .collect()
-            )
+            ),
+            &HashSet::new()
         )?,
         (
             vec![(0, 1.0), (6, 2.5), (7, 1.0)].into_iter().collect(),
             4.5
         )
     );
+    assert_eq!(
+        preallocate_hours(
+            Local.ymd(2021, 3, 14),
+            13,
+            &WorkDaysInput::DayHours(
+                vec![
+                    (WorkDayInput::Weekday(Weekday::Sun), 1.0),
+                    (WorkDayInput::Weekday(Weekday::Sat), 2.5)
+                ]
+                .into_iter()
+                .collect()
+            ),
+            &vec![
+                NaiveDate::from_ymd(2021, 3, 16),
+                NaiveDate::from_ymd(2021, 3, 20),
+                NaiveDate::from_ymd(2021, 3, 30)
+            ]
+            .into_iter()
+            .collect()
+        )?,
+        (
+            vec![(0, 1.0), (2, 0.0), (6, 0.0), (7, 1.0)]
+                .into_iter()
+                .collect(),
+            2.0
+        )
+    );
+    Ok(())
+}
+
+fn holiday_dates(holidays_input: &[HolidayInput]) -> HashSet<NaiveDate> {
+    let mut result = HashSet::new();
+    for holiday_input in holidays_input {
+        match holiday_input {
+            HolidayInput::Date(date) => {
+                result.insert(*date);
+            }
+            HolidayInput::Range { from, to } => {
+                let mut date = *from;
+                while date <= *to {
+                    result.insert(date);
+                    date = date.succ();
+                }
+            }
+        }
+    }
+    result
+}
+
+#[test]
+fn test_holiday_dates() {
+    assert_eq!(
+        holiday_dates(&[
+            HolidayInput::Date(NaiveDate::from_ymd(2021, 4, 2)),
+            HolidayInput::Range {
+                from: NaiveDate::from_ymd(2021, 12, 30),
+                to: NaiveDate::from_ymd(2022, 1, 1)
+            }
+        ]),
+        vec![
+            NaiveDate::from_ymd(2021, 4, 2),
+            NaiveDate::from_ymd(2021, 12, 30),
+            NaiveDate::from_ymd(2021, 12, 31),
+            NaiveDate::from_ymd(2022, 1, 1)
+        ]
+        .into_iter()
+        .collect()
+    );
+}
+
+/// Where an expanded period comes from, as an index into the input's
+/// `schedules` or `periods`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum PeriodSource {
+    Schedule(usize),
+    Period(usize),
+}
+
+/// The period a schedule generates starting on `start`
+pub(crate) fn schedule_period(schedule_input: &ScheduleInput, start: NaiveDate) -> PeriodInput {
+    PeriodInput {
+        start,
+        length: schedule_input.length,
+        work_days: schedule_input.work_days.clone(),
+        work_hours: schedule_input.work_hours,
+        holiday_calendar: schedule_input.holiday_calendar.clone(),
+        clients: schedule_input.clients.clone(),
+        tags: schedule_input.tags.clone(),
+        overhead: schedule_input.overhead.clone(),
+    }
+}
+
+/// Expand the recurring schedules into periods, through the period containing
+/// `today` for schedules without an end date.  Explicitly listed periods
+/// replace generated periods with the same start date.
+pub(crate) fn expand_periods(today: NaiveDate, input: &Input) -> Result<Vec<PeriodInput>> {
+    Ok(expand_period_sources(today, input)?
+        .into_iter()
+        .map(|(_, v)| v)
+        .collect())
+}
+
+/// Like `expand_periods`, but also returns where each period comes from
+pub(crate) fn expand_period_sources(
+    today: NaiveDate,
+    input: &Input,
+) -> Result<Vec<(PeriodSource, PeriodInput)>> {
+    let mut periods = BTreeMap::new();
+    for (index, schedule_input) in input.schedules.iter().enumerate() {
+        let length = schedule_input.length.unwrap_or_else(|| {
+            input
+                .defaults
+                .period_length
+                .unwrap_or(DEFAULT_PERIOD_LENGTH)
+        });
+        if length <= 0 {
+            bail!(
+                "Schedule starting {} must have a positive length",
+                schedule_input.start
+            );
+        }
+        let end = schedule_input.end.unwrap_or(today);
+        let mut start = schedule_input.start;
+        while start <= end {
+            periods.insert(
+                start,
+                (
+                    PeriodSource::Schedule(index),
+                    schedule_period(schedule_input, start),
+                ),
+            );
+            start += Duration::days(length);
+        }
+    }
+    for (index, period_input) in input.periods.iter().enumerate() {
+        periods.insert(
+            period_input.start,
+            (PeriodSource::Period(index), period_input.clone()),
+        );
+    }
+    Ok(periods.into_iter().map(|(_, v)| v).collect())
+}
+
+#[test]
+fn test_expand_periods() -> Result<()> {
+    let input: Input = serde_yaml::from_str(
+        "
+defaults:
+  period-length: 14
+schedules:
+- start: 2021-01-03
+  end: 2021-01-31
+  clients:
+    Old:
+      expected-hours: 10
+- start: 2021-03-07
+  length: 7
+  clients:
+    New:
+      expected-hours: 20
+periods:
+- start: 2021-03-14
+  clients:
+    Override:
+      expected-hours: 5
+",
+    )?;
+    let periods = expand_periods(NaiveDate::from_ymd(2021, 3, 24), &input)?;
+    assert_eq!(
+        periods
+            .iter()
+            .map(|v| {
+                let mut clients: Vec<_> = v.clients.keys().map(String::as_str).collect();
+                clients.sort_unstable();
+                (v.start, v.length, clients)
+            })
+            .collect::<Vec<_>>(),
+        vec![
+            (NaiveDate::from_ymd(2021, 1, 3), None, vec!["Old"]),
+            (NaiveDate::from_ymd(2021, 1, 17), None, vec!["Old"]),
+            (NaiveDate::from_ymd(2021, 1, 31), None, vec!["Old"]),
+            (NaiveDate::from_ymd(2021, 3, 7), Some(7), vec!["New"]),
+            (NaiveDate::from_ymd(2021, 3, 14), None, vec!["Override"]),
+            (NaiveDate::from_ymd(2021, 3, 21), Some(7), vec!["New"]),
+        ]
+    );
     Ok(())
 }
 
+#[derive(Clone, Debug, PartialEq)]
+struct PeriodAllocation {
+    partial_percent: f64,
+    last_work_day_offset: i64,
+    day_percents: Vec<f64>,
+}
+
+/// Period-level settings shared by the period's clients and projects
+struct PeriodContext {
+    start: Date<Local>,
+    length: i64,
+    holidays: HashSet<NaiveDate>,
+    work_hours: Option<WorkHoursInput>,
+    allocation: PeriodAllocation,
+}
+
+/// How much of the day's work hours have passed at `time`, or all of them if
+/// no work hours are specified
+fn work_hours_fraction(time: NaiveTime, work_hours: Option<&WorkHoursInput>) -> f64 {
+    match work_hours {
+        Some(WorkHoursInput { from, to }) if to.0 > from.0 => {
+            let elapsed = (time - from.0).num_seconds() as f64;
+            let total = (to.0 - from.0).num_seconds() as f64;
+            (elapsed / total).max(0.0).min(1.0)
+        }
+        _ => 1.0,
+    }
+}
+
+#[test]
+fn test_work_hours_fraction() {
+    let work_hours = WorkHoursInput {
+        from: TimeOfDayInput(NaiveTime::from_hms(9, 0, 0)),
+        to: TimeOfDayInput(NaiveTime::from_hms(17, 0, 0)),
+    };
+    let times = [(8, 0), (9, 0), (11, 0), (16, 30), (17, 0), (23, 0)];
+    let fractions = |work_hours: Option<&WorkHoursInput>| {
+        times
+            .iter()
+            .map(|(hour, minute)| {
+                work_hours_fraction(NaiveTime::from_hms(*hour, *minute, 0), work_hours)
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        fractions(Some(&work_hours)),
+        vec![0.0, 0.0, 0.25, 0.9375, 1.0, 1.0]
+    );
+    assert_eq!(fractions(None), vec![1.0; 6]);
+}
+
 fn calculate_partial_period_hours_percent(
     now: DateTime<Local>,
     period_start: Date<Local>,
     period_length: i64,
-    clients: &HashMap<String, ClientInput>,
+    total_expected_hours: f64,
     work_days_input: &WorkDaysInput,
-) -> Result<(f64, i64)> {
+    holidays: &HashSet<NaiveDate>,
+    work_hours: Option<&WorkHoursInput>,
+) -> Result<PeriodAllocation> {
     let (offset_preallocated_hours, total_preallocated_hours) =
-        preallocate_hours(period_start, period_length, work_days_input)?;
+        preallocate_hours(period_start, period_length, work_days_input, holidays)?;
     let today_offset = (now.date() - period_start).num_days();
-    let total_expected_hours: f64 = clients.values().map(|v| v.expected_hours).sum();
+    // With fewer expected hours than the work days allocate (e.g. none, when
+    // every client has its own work days), the days keep the work days'
+    // proportions, or are spread evenly if the work days allocate no hours
+    let total_hours =
+        if total_expected_hours > 0.0 && total_expected_hours >= total_preallocated_hours {
+            total_expected_hours
+        } else if total_preallocated_hours > 0.0 {
+            total_preallocated_hours
+        } else {
+            (period_length - offset_preallocated_hours.len() as i64) as f64
+        };
     let mut partial_percent = 0.0;
     let mut last_work_day_offset = 0;
+    let mut day_percents = Vec::new();
     info!("Daily hours for period starting: {}", period_start);
     for offset in 0..period_length {
         let hours = match offset_preallocated_hours.get(&offset) {
             Some(preallocated_hours) => *preallocated_hours,
             None => {
-                (total_expected_hours - total_preallocated_hours)
+                (total_hours - total_preallocated_hours)
                     / (period_length - offset_preallocated_hours.len() as i64) as f64
             }
         };
-        info!(
-            "  {}: {} ({:.1}%)",
-            offset,
-            hours,
-            hours * 100.0 / total_expected_hours
-        );
-        if offset <= today_offset {
-            partial_percent += hours / total_expected_hours;
+        let day_percent = if total_hours > 0.0 {
+            hours / total_hours
+        } else {
+            0.0
+        };
+        info!("  {}: {} ({:.1}%)", offset, hours, day_percent * 100.0);
+        match offset.cmp(&today_offset) {
+            Ordering::Less => partial_percent += day_percent,
+            Ordering::Equal => {
+                partial_percent += day_percent * work_hours_fraction(now.time(), work_hours)
+            }
+            Ordering::Greater => (),
         }
+        day_percents.push(day_percent);
         if hours > 0.0 {
             last_work_day_offset = offset;
         }
@@ -281,7 +553,11 @@ fn calculate_partial_period_hours_percent(
         "  partial_percent={} last_work_day_offset={} total_expected_hours={} today_offset={}",
         partial_percent, last_work_day_offset, total_expected_hours, today_offset
     );
-    Ok((partial_percent, last_work_day_offset))
+    Ok(PeriodAllocation {
+        partial_percent,
+        last_work_day_offset,
+        day_percents,
+    })
 }
 
 #[test]
@@ -291,24 +567,7 @@ fn test_calculate_partial_period_hours_percent() -> Result<()> {
             Local.ymd(2021, 3, 12).and_hms(12, 0, 0),
             Local.ymd(2021, 3, 7),
             10,
-            &vec![
-                (
-                    "Client 1".to_string(),
-                    ClientInput {
-                        expected_hours: 30.0,
-                        projects: None
-                    }
-                ),
-                (
-                    "Client 2".to_string(),
-                    ClientInput {
-                        expected_hours: 10.0,
-                        projects: None
-                    }
-                )
-            ]
-            .into_iter()
-            .collect(),
+            40.0,
             &WorkDaysInput::DayHours(
                 vec![
                     (WorkDayInput::Weekday(Weekday::Sun), 2.0),
@@ -317,39 +576,357 @@ fn test_calculate_partial_period_hours_percent() -> Result<()> {
                 ]
                 .into_iter()
                 .collect()
-            )
+            ),
+            &HashSet::new(),
+            None
         )?,
-        (0.6875, 8)
+        PeriodAllocation {
+            partial_percent: 0.6875,
+            last_work_day_offset: 8,
+            day_percents: vec![0.05, 0.075, 0.0, 0.1875, 0.1875, 0.1875, 0.1875, 0.05, 0.075, 0.0],
+        }
     );
     assert_eq!(
         calculate_partial_period_hours_percent(
             Local.ymd(2021, 3, 14).and_hms(12, 0, 0),
             Local.ymd(2021, 3, 7),
             7,
-            &vec![(
-                "Client".to_string(),
-                ClientInput {
-                    expected_hours: 40.0,
-                    projects: None
-                }
-            )]
-            .into_iter()
-            .collect(),
+            40.0,
+            &WorkDaysInput::FromToWeekdays {
+                from: Weekday::Mon,
+                to: Weekday::Fri
+            },
+            &HashSet::new(),
+            None
+        )?,
+        PeriodAllocation {
+            partial_percent: 1.0,
+            last_work_day_offset: 5,
+            day_percents: vec![0.0, 0.2, 0.2, 0.2, 0.2, 0.2, 0.0],
+        }
+    );
+    assert_eq!(
+        calculate_partial_period_hours_percent(
+            Local.ymd(2021, 3, 9).and_hms(12, 0, 0),
+            Local.ymd(2021, 3, 7),
+            7,
+            40.0,
+            &WorkDaysInput::FromToWeekdays {
+                from: Weekday::Mon,
+                to: Weekday::Fri
+            },
+            &vec![NaiveDate::from_ymd(2021, 3, 10)].into_iter().collect(),
+            None
+        )?,
+        PeriodAllocation {
+            partial_percent: 0.5,
+            last_work_day_offset: 5,
+            day_percents: vec![0.0, 0.25, 0.25, 0.0, 0.25, 0.25, 0.0],
+        }
+    );
+    assert_eq!(
+        calculate_partial_period_hours_percent(
+            Local.ymd(2021, 3, 9).and_hms(12, 0, 0),
+            Local.ymd(2021, 3, 7),
+            7,
+            0.0,
             &WorkDaysInput::FromToWeekdays {
                 from: Weekday::Mon,
                 to: Weekday::Fri
             },
+            &HashSet::new(),
+            None
+        )?,
+        PeriodAllocation {
+            partial_percent: 0.4,
+            last_work_day_offset: 5,
+            day_percents: vec![0.0, 0.2, 0.2, 0.2, 0.2, 0.2, 0.0],
+        }
+    );
+    assert_eq!(
+        calculate_partial_period_hours_percent(
+            Local.ymd(2021, 3, 9).and_hms(12, 0, 0),
+            Local.ymd(2021, 3, 7),
+            7,
+            0.0,
+            &WorkDaysInput::DayHours(
+                vec![
+                    (WorkDayInput::Weekday(Weekday::Mon), 3.0),
+                    (WorkDayInput::Weekday(Weekday::Wed), 1.0)
+                ]
+                .into_iter()
+                .collect()
+            ),
+            &HashSet::new(),
+            None
+        )?,
+        PeriodAllocation {
+            partial_percent: 0.75,
+            last_work_day_offset: 3,
+            day_percents: vec![0.0, 0.75, 0.0, 0.25, 0.0, 0.0, 0.0],
+        }
+    );
+    assert_eq!(
+        calculate_partial_period_hours_percent(
+            Local.ymd(2021, 3, 9).and_hms(13, 0, 0),
+            Local.ymd(2021, 3, 7),
+            7,
+            40.0,
+            &WorkDaysInput::FromToWeekdays {
+                from: Weekday::Mon,
+                to: Weekday::Thu
+            },
+            &HashSet::new(),
+            Some(&WorkHoursInput {
+                from: TimeOfDayInput(NaiveTime::from_hms(9, 0, 0)),
+                to: TimeOfDayInput(NaiveTime::from_hms(17, 0, 0))
+            })
         )?,
-        (1.0, 5)
+        PeriodAllocation {
+            partial_percent: 0.375,
+            last_work_day_offset: 4,
+            day_percents: vec![0.0, 0.25, 0.25, 0.25, 0.25, 0.0, 0.0],
+        }
+    );
+    Ok(())
+}
+
+/// Calculate how much the expected hours summed over all of a bucket's periods
+/// must change so that only the over/under hours allowed by each period's
+/// rollover policy are carried over into it, and hours within a period's range
+/// are not carried over at all.  Returns the adjustment for the expected hours,
+/// and for the partial expected hours, which leaves out hours within the range
+/// of a period that has not ended yet.
+fn calculate_rollover_adjustment(
+    today: Date<Local>,
+    sources: &[&PeriodBucketDurations],
+) -> (Duration, Duration) {
+    let mut full_balance = Duration::zero();
+    let mut balance = Duration::zero();
+    let mut unended_range_adjustment = Duration::zero();
+    let mut period_balances = Vec::new();
+    for source in sources {
+        if source.period_start <= today {
+            balance = match source.rollover {
+                Rollover::Full => balance,
+                Rollover::None => Duration::zero(),
+                Rollover::MaxHours(max) => balance.max(-max).min(max),
+                Rollover::Window(periods) => period_balances
+                    .iter()
+                    .rev()
+                    .take(periods)
+                    .fold(Duration::zero(), |acc, v| acc + *v),
+            };
+        }
+        full_balance = full_balance + source.durations.remaining();
+        let carried = balance;
+        balance = source.balance(carried);
+        if source.period_start + Duration::days(source.period_length) > today {
+            unended_range_adjustment =
+                unended_range_adjustment + carried + source.durations.remaining() - balance;
+        }
+        period_balances.push(source.balance(Duration::zero()));
+    }
+    let adjustment = full_balance - balance;
+    (adjustment, adjustment - unended_range_adjustment)
+}
+
+#[test]
+fn test_calculate_rollover_adjustment() {
+    let source = |day, expected_hours, actual_hours, rollover| {
+        let mut durations = Durations::zero();
+        durations.expected = Duration::hours(expected_hours);
+        durations.actual = Duration::hours(actual_hours);
+        PeriodBucketDurations {
+            period_start: Local.ymd(2021, 3, day),
+            bucket: client_bucket("Client"),
+            period_length: 7,
+            last_work_day_offset: 5,
+            durations,
+            max_expected: MaxExpected::Exact,
+            day_expected: Vec::new(),
+            day_actual: Vec::new(),
+            rollover,
+            count: CountInput::All,
+        }
+    };
+    let adjustment = |rollover| {
+        let sources = vec![
+            source(7, 10, 5, rollover),
+            source(14, 10, 7, rollover),
+            source(21, 10, 2, rollover),
+        ];
+        let source_refs: Vec<_> = sources.iter().collect();
+        calculate_rollover_adjustment(Local.ymd(2021, 3, 23), &source_refs)
+    };
+    assert_eq!(
+        adjustment(Rollover::Full),
+        (Duration::zero(), Duration::zero())
+    );
+    assert_eq!(
+        adjustment(Rollover::None),
+        (Duration::hours(-8), Duration::hours(-8))
+    );
+    assert_eq!(
+        adjustment(Rollover::MaxHours(Duration::hours(4))),
+        (Duration::hours(-4), Duration::hours(-4))
+    );
+    assert_eq!(
+        adjustment(Rollover::Window(1)),
+        (Duration::hours(-5), Duration::hours(-5))
+    );
+    let range_sources: Vec<_> = [(7, 5), (14, 12), (21, 20)]
+        .iter()
+        .map(|&(day, actual_hours)| PeriodBucketDurations {
+            max_expected: MaxExpected::Range(Some(Duration::hours(15))),
+            ..source(day, 10, actual_hours, Rollover::Full)
+        })
+        .collect();
+    let range_source_refs: Vec<_> = range_sources.iter().collect();
+    assert_eq!(
+        calculate_rollover_adjustment(Local.ymd(2021, 3, 23), &range_source_refs),
+        (Duration::hours(5), Duration::zero())
+    );
+}
+
+/// Resolve a client or project's hours into the expected hours (the lower bound
+/// for a range) and their upper bound
+pub(crate) fn resolve_hours_range(
+    expected_hours: Option<f64>,
+    min_hours: Option<f64>,
+    max_hours: Option<f64>,
+) -> Result<(f64, MaxExpected)> {
+    match (expected_hours, min_hours, max_hours) {
+        (Some(expected_hours), None, None) => Ok((expected_hours, MaxExpected::Exact)),
+        (Some(_), _, _) => {
+            bail!("'expected-hours' can not be combined with 'min-hours' or 'max-hours'")
+        }
+        (None, None, None) => {
+            bail!("One of 'expected-hours', 'min-hours' or 'max-hours' is required")
+        }
+        (None, min_hours, max_hours) => {
+            let min_hours = min_hours.unwrap_or(0.0);
+            match max_hours {
+                Some(max_hours) if max_hours < min_hours => {
+                    bail!("'max-hours' can not be less than 'min-hours'")
+                }
+                Some(max_hours) => Ok((
+                    min_hours,
+                    MaxExpected::Range(Some(hours_duration(max_hours))),
+                )),
+                None => Ok((min_hours, MaxExpected::Range(None))),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_resolve_hours_range() -> Result<()> {
+    assert_eq!(
+        resolve_hours_range(Some(10.0), None, None)?,
+        (10.0, MaxExpected::Exact)
+    );
+    assert_eq!(
+        resolve_hours_range(None, Some(10.0), Some(15.0))?,
+        (10.0, MaxExpected::Range(Some(Duration::hours(15))))
+    );
+    assert_eq!(
+        resolve_hours_range(None, None, Some(15.0))?,
+        (0.0, MaxExpected::Range(Some(Duration::hours(15))))
+    );
+    assert_eq!(
+        resolve_hours_range(None, Some(10.0), None)?,
+        (10.0, MaxExpected::Range(None))
+    );
+    assert!(resolve_hours_range(Some(10.0), Some(10.0), None).is_err());
+    assert!(resolve_hours_range(None, None, None).is_err());
+    assert!(resolve_hours_range(None, Some(15.0), Some(10.0)).is_err());
+    Ok(())
+}
+
+/// Resolve a project's hours like `resolve_hours_range`, or as a percentage of
+/// its client's expected hours
+pub(crate) fn resolve_project_hours_range(
+    project_input: &ProjectInput,
+    client_expected_hours: f64,
+) -> Result<(f64, MaxExpected)> {
+    match project_input.expected_percent {
+        Some(_)
+            if project_input.expected_hours.is_some()
+                || project_input.min_hours.is_some()
+                || project_input.max_hours.is_some() =>
+        {
+            bail!("'expected-percent' can not be combined with hours")
+        }
+        Some(percent) if !(0.0..=100.0).contains(&percent) => {
+            bail!("'expected-percent' must be between 0 and 100")
+        }
+        Some(percent) => Ok((client_expected_hours * percent / 100.0, MaxExpected::Exact)),
+        None => resolve_hours_range(
+            project_input.expected_hours,
+            project_input.min_hours,
+            project_input.max_hours,
+        ),
+    }
+}
+
+#[test]
+fn test_resolve_project_hours_range() -> Result<()> {
+    let project_input = |yaml| -> Result<ProjectInput> { Ok(serde_yaml::from_str(yaml)?) };
+    assert_eq!(
+        resolve_project_hours_range(&project_input("expected-percent: 25")?, 20.0)?,
+        (5.0, MaxExpected::Exact)
+    );
+    assert_eq!(
+        resolve_project_hours_range(&project_input("expected-hours: 3")?, 20.0)?,
+        (3.0, MaxExpected::Exact)
     );
+    assert!(resolve_project_hours_range(
+        &project_input("{expected-percent: 25, expected-hours: 3}")?,
+        20.0
+    )
+    .is_err());
+    assert!(resolve_project_hours_range(&project_input("expected-percent: 120")?, 20.0).is_err());
     Ok(())
 }
 
+fn new_period_bucket_durations(
+    period: &PeriodContext,
+    bucket: Bucket,
+    expected_hours: f64,
+    max_expected: MaxExpected,
+    allocation: &PeriodAllocation,
+    rollover: Rollover,
+    count: CountInput,
+) -> PeriodBucketDurations {
+    PeriodBucketDurations {
+        period_start: period.start,
+        bucket,
+        period_length: period.length,
+        last_work_day_offset: allocation.last_work_day_offset,
+        durations: Durations::expected(expected_hours, allocation.partial_percent),
+        max_expected,
+        day_expected: allocation
+            .day_percents
+            .iter()
+            .map(|v| hours_duration(expected_hours * v))
+            .collect(),
+        day_actual: vec![Duration::zero(); period.length as usize],
+        rollover,
+        count,
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Processor {
     now: DateTime<Local>,
     period_bucket_durations: Vec<PeriodBucketDurations>,
-    found_warning: bool,
+    matchers: NameMatchers,
+    unmatched: BTreeMap<UnmatchedKey, Duration>,
+    diagnostics: Vec<Diagnostic>,
+    running: HashMap<Bucket, Duration>,
+    count_overlaps_once: bool,
+    long_running_threshold: Option<Duration>,
 }
 
 impl Processor {
@@ -357,77 +934,291 @@ impl Processor {
         Processor {
             now,
             period_bucket_durations: Vec::new(),
-            found_warning: false,
+            matchers: NameMatchers::default(),
+            unmatched: BTreeMap::new(),
+            diagnostics: Vec::new(),
+            running: HashMap::new(),
+            count_overlaps_once: false,
+            long_running_threshold: None,
+        }
+    }
+
+    /// Only count the time where time entries overlap once, for the earliest
+    /// starting entry
+    pub fn set_count_overlaps_once(&mut self, count_overlaps_once: bool) {
+        self.count_overlaps_once = count_overlaps_once;
+    }
+
+    /// Warn about timers that have been running for longer than the threshold
+    pub fn set_long_running_threshold(&mut self, long_running_threshold: Option<Duration>) {
+        self.long_running_threshold = long_running_threshold;
+    }
+
+    fn bucket_allocation(
+        &self,
+        period: &PeriodContext,
+        expected_hours: f64,
+        work_days_input: Option<&WorkDaysInput>,
+        inherited_allocation: &PeriodAllocation,
+    ) -> Result<PeriodAllocation> {
+        match work_days_input {
+            Some(work_days_input) => calculate_partial_period_hours_percent(
+                self.now,
+                period.start,
+                period.length,
+                expected_hours,
+                work_days_input,
+                &period.holidays,
+                period.work_hours.as_ref(),
+            ),
+            None => Ok(inherited_allocation.clone()),
         }
     }
 
     fn initialize_period_client(
         &mut self,
-        period_start: Date<Local>,
-        period_length: i64,
-        last_work_day_offset: i64,
-        partial_percent: f64,
+        period: &PeriodContext,
+        defaults_input: &DefaultsInput,
         client_name: String,
         client_input: ClientInput,
-    ) {
+    ) -> Result<()> {
+        let rollover = client_input
+            .rollover
+            .or(defaults_input.rollover)
+            .map(Rollover::from)
+            .unwrap_or(Rollover::Full);
+        let count = client_input.count.unwrap_or(CountInput::All);
+        self.matchers
+            .add_client(&client_name, &client_input.aliases)?;
+        let (client_expected_hours, client_max_expected) = resolve_hours_range(
+            client_input.expected_hours,
+            client_input.min_hours,
+            client_input.max_hours,
+        )
+        .with_context(|| format!("Invalid hours for client: {}", client_name))?;
+        let client_allocation = self.bucket_allocation(
+            period,
+            client_expected_hours,
+            client_input.work_days.as_ref(),
+            &period.allocation,
+        )?;
         if let Some(projects) = client_input.projects {
+            let total_percent: f64 = projects.values().filter_map(|v| v.expected_percent).sum();
+            if total_percent > 100.0 {
+                bail!(
+                    "Project percentages for client {} add up to more than 100%: {}%",
+                    client_name,
+                    total_percent
+                );
+            }
             for (project_name, project_input) in projects {
-                self.period_bucket_durations.push(PeriodBucketDurations {
-                    period_start,
-                    bucket: Bucket {
-                        client: client_name.clone(),
-                        project: Some(project_name),
-                    },
-                    period_length,
-                    last_work_day_offset,
-                    durations: Durations::expected(project_input.expected_hours, partial_percent),
-                });
+                self.matchers
+                    .add_project(&client_name, &project_name, &project_input.aliases)?;
+                let (project_expected_hours, project_max_expected) =
+                    resolve_project_hours_range(&project_input, client_expected_hours)
+                        .with_context(|| {
+                            format!(
+                                "Invalid hours for project: {} / {}",
+                                client_name, project_name
+                            )
+                        })?;
+                let project_allocation = self.bucket_allocation(
+                    period,
+                    project_expected_hours,
+                    project_input.work_days.as_ref(),
+                    &client_allocation,
+                )?;
+                self.period_bucket_durations
+                    .push(new_period_bucket_durations(
+                        period,
+                        Bucket {
+                            client: Some(client_name.clone()),
+                            project: Some(project_name),
+                            tag: None,
+                            no_project: false,
+                        },
+                        project_expected_hours,
+                        project_max_expected,
+                        &project_allocation,
+                        rollover,
+                        project_input.count.unwrap_or(count),
+                    ));
             }
         }
-        self.period_bucket_durations.push(PeriodBucketDurations {
-            period_start,
-            bucket: Bucket {
-                client: client_name,
-                project: None,
-            },
-            period_length,
-            last_work_day_offset,
-            durations: Durations::expected(client_input.expected_hours, partial_percent),
-        });
+        if let Some(tags) = client_input.tags {
+            for (tag_name, tag_input) in tags {
+                self.period_bucket_durations
+                    .push(new_period_bucket_durations(
+                        period,
+                        Bucket {
+                            client: Some(client_name.clone()),
+                            project: None,
+                            tag: Some(tag_name),
+                            no_project: false,
+                        },
+                        tag_input.expected_hours,
+                        MaxExpected::Exact,
+                        &client_allocation,
+                        rollover,
+                        count,
+                    ));
+            }
+        }
+        // Time entries without a project are tracked but not expected, so none
+        // is carried over
+        self.period_bucket_durations
+            .push(new_period_bucket_durations(
+                period,
+                Bucket::no_project(&client_name),
+                0.0,
+                MaxExpected::Exact,
+                &client_allocation,
+                Rollover::None,
+                count,
+            ));
+        self.period_bucket_durations
+            .push(new_period_bucket_durations(
+                period,
+                Bucket {
+                    client: Some(client_name),
+                    project: None,
+                    tag: None,
+                    no_project: false,
+                },
+                client_expected_hours,
+                client_max_expected,
+                &client_allocation,
+                rollover,
+                count,
+            ));
+        Ok(())
     }
 
     pub fn initialize(&mut self, input: Input) -> Result<()> {
-        for period_input in input.periods {
+        let defaults_input = &input.defaults;
+        let holidays = holiday_dates(&input.holidays);
+        let mut calendar_holidays: HashMap<String, HashSet<NaiveDate>> = HashMap::new();
+        for period_input in expand_periods(self.now.date().naive_local(), &input)? {
             let period_start = Local.from_local_date(&period_input.start).unwrap();
-            let defaults_input = &input.defaults;
             let period_length = period_input.length.unwrap_or_else(|| {
                 defaults_input
                     .period_length
                     .unwrap_or(DEFAULT_PERIOD_LENGTH)
             });
+            if period_length <= 0 {
+                bail!(
+                    "Period starting {} must have a positive length",
+                    period_input.start
+                );
+            }
             let work_days_input = period_input.work_days.as_ref().unwrap_or_else(|| {
                 defaults_input
                     .work_days
                     .as_ref()
                     .unwrap_or(&DEFAULT_WORK_DAYS)
             });
-            let (partial_percent, last_work_day_offset) = calculate_partial_period_hours_percent(
+            let period_holidays = match period_input
+                .holiday_calendar
+                .as_ref()
+                .or_else(|| defaults_input.holiday_calendar.as_ref())
+            {
+                Some(holiday_calendar) => {
+                    if let hash_map::Entry::Vacant(entry) =
+                        calendar_holidays.entry(holiday_calendar.clone())
+                    {
+                        entry.insert(load_ical_holidays(holiday_calendar)?);
+                    }
+                    holidays
+                        .union(&calendar_holidays[holiday_calendar])
+                        .cloned()
+                        .collect()
+                }
+                None => holidays.clone(),
+            };
+            let work_hours = period_input.work_hours.or(defaults_input.work_hours);
+            // Clients with their own work days are allocated separately
+            let mut shared_expected_hours = 0.0;
+            for (client_name, client_input) in &period_input.clients {
+                if client_input.work_days.is_none() {
+                    let (expected_hours, _) = resolve_hours_range(
+                        client_input.expected_hours,
+                        client_input.min_hours,
+                        client_input.max_hours,
+                    )
+                    .with_context(|| format!("Invalid hours for client: {}", client_name))?;
+                    shared_expected_hours += expected_hours;
+                }
+            }
+            let allocation = calculate_partial_period_hours_percent(
                 self.now,
                 period_start,
                 period_length,
-                &period_input.clients,
+                shared_expected_hours,
                 work_days_input,
+                &period_holidays,
+                work_hours.as_ref(),
             )?;
+            let period = PeriodContext {
+                start: period_start,
+                length: period_length,
+                holidays: period_holidays,
+                work_hours,
+                allocation,
+            };
             for (client_name, client_input) in period_input.clients {
-                self.initialize_period_client(
-                    period_start,
-                    period_length,
-                    last_work_day_offset,
-                    partial_percent,
-                    client_name,
-                    client_input,
-                );
+                self.initialize_period_client(&period, defaults_input, client_name, client_input)?;
+            }
+            let rollover = defaults_input
+                .rollover
+                .map(Rollover::from)
+                .unwrap_or(Rollover::Full);
+            if let Some(tags) = period_input.tags {
+                for (tag_name, tag_input) in tags {
+                    let allocation = self.bucket_allocation(
+                        &period,
+                        tag_input.expected_hours,
+                        Some(work_days_input),
+                        &period.allocation,
+                    )?;
+                    self.period_bucket_durations
+                        .push(new_period_bucket_durations(
+                            &period,
+                            Bucket {
+                                client: None,
+                                project: None,
+                                tag: Some(tag_name),
+                                no_project: false,
+                            },
+                            tag_input.expected_hours,
+                            MaxExpected::Exact,
+                            &allocation,
+                            rollover,
+                            CountInput::All,
+                        ));
+                }
             }
+            // Without an overhead budget, unassigned time is tracked but not
+            // expected, so none is carried over
+            let (overhead_expected_hours, overhead_rollover) = match period_input.overhead {
+                Some(overhead_input) => (overhead_input.expected_hours, rollover),
+                None => (0.0, Rollover::None),
+            };
+            let overhead_allocation = self.bucket_allocation(
+                &period,
+                overhead_expected_hours,
+                Some(work_days_input),
+                &period.allocation,
+            )?;
+            self.period_bucket_durations
+                .push(new_period_bucket_durations(
+                    &period,
+                    Bucket::unassigned(),
+                    overhead_expected_hours,
+                    MaxExpected::Exact,
+                    &overhead_allocation,
+                    overhead_rollover,
+                    CountInput::All,
+                ));
         }
         self.period_bucket_durations.sort();
         debug!(
@@ -437,7 +1228,7 @@ impl Processor {
         Ok(())
     }
 
-    fn get_time_entries(&self, toggl: &Toggl) -> Result<Vec<TimeEntry>> {
+    fn get_time_entries(&self, source: &dyn TimeEntrySource) -> Result<Vec<Entry>> {
         let min_period_start = self
             .period_bucket_durations
             .iter()
@@ -445,109 +1236,278 @@ impl Processor {
             .min()
             .unwrap_or_else(|| self.now.date());
         debug!("min_period_start: {:#?}", min_period_start);
-        let time_entries = toggl
-            .get_time_entries_range(
-                Some(min_period_start.and_hms(0, 0, 0).with_timezone(&Utc)),
-                Some(self.now.with_timezone(&Utc)),
-            )
-            .context("Could not get time entries from Toggl")?;
-        Ok(time_entries)
+        source.fetch_time_entries(
+            min_period_start.and_hms(0, 0, 0).with_timezone(&Utc),
+            self.now.with_timezone(&Utc),
+        )
     }
 
     fn accumulate_time_entry(
         &mut self,
         start: DateTime<Local>,
         stop: DateTime<Local>,
-        time_entry: &TimeEntry,
-    ) -> Result<()> {
+        time_entry: &Entry,
+    ) -> Result<Option<DiagnosticKind>> {
         let duration = stop - start;
-        if let (Some(client), Some(project)) = (&time_entry.client, &time_entry.project) {
-            let mut found_match_client_only = false;
-            let mut found_match_project = false;
-            for PeriodBucketDurations {
-                period_start,
-                bucket,
-                period_length,
-                last_work_day_offset: _,
-                durations,
-            } in &mut self.period_bucket_durations
-            {
-                if client.name == bucket.client
-                    && (bucket.project.is_none() || Some(&project.name) == bucket.project.as_ref())
-                    && is_date_in_period(start.date(), *period_start, *period_length)
-                {
-                    if bucket.project.is_none() {
-                        if found_match_client_only {
-                            bail!(
-                                "Multiple expected clients/periods matched: {:?}",
-                                &time_entry
-                            );
-                        } else {
-                            found_match_client_only = true;
-                        }
-                    } else if found_match_project {
-                        bail!(
-                            "Multiple expected projects/periods matched: {:?}",
-                            &time_entry
-                        );
-                    } else {
-                        found_match_project = true;
+        let running = match time_entry.stop {
+            Some(_) => None,
+            None => Some(self.now - time_entry.start.with_timezone(&Local)),
+        };
+        let mut in_period = false;
+        let mut found_match_client: Option<String> = None;
+        let mut found_match_project: Option<String> = None;
+        let mut found_match_tags = HashSet::new();
+        let mut found_unassigned = false;
+        for PeriodBucketDurations {
+            period_start,
+            bucket,
+            period_length,
+            last_work_day_offset: _,
+            durations,
+            max_expected: _,
+            day_expected: _,
+            day_actual,
+            rollover: _,
+            count,
+        } in &mut self.period_bucket_durations
+        {
+            let is_entry_period = is_date_in_period(start.date(), *period_start, *period_length);
+            in_period |= is_entry_period;
+            let bucket_matches = if bucket.is_unassigned() {
+                time_entry.client.is_none()
+            } else if bucket.tag.is_some() {
+                self.matchers.bucket_matches(bucket, time_entry)
+            } else {
+                // Entries with a client but no project only count toward the
+                // client's (no project) bucket
+                time_entry.client.is_some()
+                    && bucket.no_project == time_entry.project.is_none()
+                    && self.matchers.bucket_matches(bucket, time_entry)
+            };
+            if bucket_matches && is_entry_period {
+                if bucket.is_unassigned() {
+                    // Only one per period, and entries in it match no client
+                    found_unassigned = true;
+                } else if let Some(tag) = &bucket.tag {
+                    if !found_match_tags.insert((bucket.client.clone(), tag.clone())) {
+                        bail!("Multiple expected tags/periods matched: {:?}", &time_entry);
                     }
-                    durations.actual = durations.actual + duration;
-                    if is_date_in_period(self.now.date(), *period_start, *period_length) {
-                        durations.current_period_actual =
-                            durations.current_period_actual + duration;
+                } else if let Some(project) = &bucket.project {
+                    if let Some(found) = &found_match_project {
+                        if found != project {
+                            bail!(
+                                "Multiple project names or aliases ({:?} and {:?}) matched: {:?}",
+                                found,
+                                project,
+                                &time_entry
+                            );
+                        }
+                        bail!(
+                            "Multiple expected projects/periods matched: {:?}",
+                            &time_entry
+                        );
+                    }
+                    found_match_project = Some(project.clone());
+                } else {
+                    let client = bucket.client_label();
+                    if let Some(found) = &found_match_client {
+                        if found != client {
+                            bail!(
+                                "Multiple client names or aliases ({:?} and {:?}) matched: {:?}",
+                                found,
+                                client,
+                                &time_entry
+                            );
+                        }
+                        bail!(
+                            "Multiple expected clients/periods matched: {:?}",
+                            &time_entry
+                        );
                     }
-                    if start.date() == self.now.date() {
-                        durations.today_actual = durations.today_actual + duration;
+                    found_match_client = Some(client.to_string());
+                }
+                if let Some(running) = running {
+                    let bucket_running = self.running.entry(bucket.clone()).or_insert(running);
+                    *bucket_running = (*bucket_running).max(running);
+                }
+                let is_current_period =
+                    is_date_in_period(self.now.date(), *period_start, *period_length);
+                if is_current_period {
+                    if time_entry.billable {
+                        durations.current_period_billable_actual =
+                            durations.current_period_billable_actual + duration;
+                    } else {
+                        durations.current_period_non_billable_actual =
+                            durations.current_period_non_billable_actual + duration;
                     }
                 }
+                if !count.includes(time_entry.billable) {
+                    continue;
+                }
+                durations.actual = durations.actual + duration;
+                let offset = (start.date() - *period_start).num_days() as usize;
+                day_actual[offset] = day_actual[offset] + duration;
+                if is_current_period {
+                    durations.current_period_actual = durations.current_period_actual + duration;
+                }
+                if start.date() == self.now.date() {
+                    durations.today_actual = durations.today_actual + duration;
+                }
             }
-            if !found_match_client_only {
-                warn!("no expected client/period matched: {:?}", &time_entry);
-                self.found_warning = true;
-            }
+        }
+        let kind = if found_unassigned || found_match_client.is_some() {
+            return Ok(None);
+        } else if time_entry.client.is_none() {
+            DiagnosticKind::MissingClient
+        } else if time_entry.project.is_none() {
+            DiagnosticKind::MissingProject
+        } else if in_period {
+            DiagnosticKind::UnmatchedEntry
         } else {
-            warn!(
-                "time entry missing client and/or project: {:?}",
-                &time_entry
-            );
-            self.found_warning = true;
+            DiagnosticKind::OutsidePeriods
+        };
+        let unmatched_duration = self
+            .unmatched
+            .entry(UnmatchedKey {
+                date: start.date().naive_local(),
+                client: time_entry.client.clone(),
+                project: time_entry.project.clone(),
+                description: time_entry.description.clone(),
+            })
+            .or_insert_with(Duration::zero);
+        *unmatched_duration = *unmatched_duration + duration;
+        Ok(Some(kind))
+    }
+
+    /// Add a diagnostic for part of a time entry, combined with the previous
+    /// diagnostic if it is the same kind for the same entry
+    fn add_diagnostic(&mut self, kind: DiagnosticKind, time_entry: &Entry, duration: Duration) {
+        if let Some(previous) = self.diagnostics.last_mut() {
+            if previous.kind == kind
+                && previous.entry_id == time_entry.id
+                && previous.start == time_entry.start
+            {
+                previous.duration = previous.duration + duration;
+                return;
+            }
         }
-        Ok(())
+        self.diagnostics.push(Diagnostic {
+            kind,
+            entry_id: time_entry.id,
+            start: time_entry.start,
+            duration,
+        });
     }
 
-    fn accumulate_time_entries(&mut self, time_entries: &[TimeEntry]) -> Result<()> {
+    fn accumulate_time_entries(&mut self, time_entries: &[Entry]) -> Result<()> {
+        let mut sorted_time_entries: Vec<_> = time_entries.iter().collect();
+        sorted_time_entries.sort_by_key(|v| v.start);
+        // The stop and id of each earlier time entry that has not stopped yet,
+        // which together cover from the current entry's start until the latest
+        // of their stops
+        let mut covering: Vec<(DateTime<Local>, Option<i64>)> = Vec::new();
         //TODO: optimize nested loops
-        for time_entry in time_entries {
+        for time_entry in sorted_time_entries {
             let time_entry_start = time_entry.start.with_timezone(&Local);
             let time_entry_stop = time_entry
                 .stop
-                .map(|v| v.with_timezone(&Local))
+                .map(|v| v.with_timezone(&Local).min(self.now))
                 .unwrap_or(self.now);
+            covering.retain(|(stop, _)| *stop > time_entry_start);
+            for (other_stop, other_entry_id) in &covering {
+                let overlap_stop = (*other_stop).min(time_entry_stop);
+                self.add_diagnostic(
+                    DiagnosticKind::OverlappingEntries {
+                        other_entry_id: *other_entry_id,
+                        overlap_start: time_entry.start,
+                        overlap_stop: overlap_stop.with_timezone(&Utc),
+                    },
+                    time_entry,
+                    overlap_stop - time_entry_start,
+                );
+            }
             let mut start = time_entry_start;
-            while start.date() != time_entry_stop.date() {
+            if self.count_overlaps_once {
+                if let Some(covered_stop) = covering.iter().map(|(stop, _)| *stop).max() {
+                    start = covered_stop.min(time_entry_stop);
+                }
+            }
+            covering.push((time_entry_stop, time_entry.id));
+            while start.date() < time_entry_stop.date() {
                 let stop = start.date().and_hms(0, 0, 0) + Duration::days(1);
-                self.accumulate_time_entry(start, stop, &time_entry)?;
+                if let Some(kind) = self.accumulate_time_entry(start, stop, time_entry)? {
+                    self.add_diagnostic(kind, time_entry, stop - start);
+                }
                 start = stop;
             }
-            self.accumulate_time_entry(start, time_entry_stop, &time_entry)?;
+            // Time entries entirely within earlier ones are not counted at all
+            if start < time_entry_stop || start == time_entry_start {
+                if let Some(kind) =
+                    self.accumulate_time_entry(start, time_entry_stop, time_entry)?
+                {
+                    self.add_diagnostic(kind, time_entry, time_entry_stop - start);
+                }
+            }
+            if time_entry.stop.is_none() {
+                let running = time_entry_stop - time_entry_start;
+                let kind = match self.long_running_threshold {
+                    Some(threshold) if running > threshold => {
+                        DiagnosticKind::LongRunningEntry { threshold }
+                    }
+                    _ => DiagnosticKind::RunningEntry,
+                };
+                self.add_diagnostic(kind, time_entry, running);
+            }
         }
         Ok(())
     }
 
-    pub fn process(&mut self, strict: bool, toggl: &Toggl) -> Result<()> {
-        let time_entries = self.get_time_entries(toggl)?;
+    /// Add up the time entries, returning diagnostics about any that are not
+    /// counted as expected
+    ///
+    /// ```
+    /// use chrono::prelude::*;
+    /// use toggl_utils::ontrack::{DiagnosticKind, Entry, Input, Processor};
+    ///
+    /// let input: Input = serde_yaml::from_str("periods: [{start: 2021-03-07, clients: {}}]")?;
+    /// let entries = vec![Entry {
+    ///     id: Some(1),
+    ///     start: Local.ymd(2021, 3, 8).and_hms(9, 0, 0).with_timezone(&Utc),
+    ///     stop: Some(Local.ymd(2021, 3, 8).and_hms(10, 0, 0).with_timezone(&Utc)),
+    ///     description: None,
+    ///     client: Some("Client".to_string()),
+    ///     project: Some("Project".to_string()),
+    ///     tags: Vec::new(),
+    ///     billable: false,
+    /// }];
+    /// let mut processor = Processor::new(Local.ymd(2021, 3, 10).and_hms(17, 0, 0));
+    /// processor.initialize(input)?;
+    /// let diagnostics = processor.process(&entries)?;
+    /// assert_eq!(diagnostics[0].kind, DiagnosticKind::UnmatchedEntry);
+    /// assert_eq!(diagnostics[0].entry_id, Some(1));
+    /// assert!(diagnostics[0].is_warning());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn process(&mut self, source: &dyn TimeEntrySource) -> Result<Vec<Diagnostic>> {
+        let time_entries = self.get_time_entries(source)?;
         debug!("time_entries: {:#?}", time_entries);
         self.accumulate_time_entries(&time_entries)?;
-        if strict && self.found_warning {
-            bail!("Strict mode enabled (see warning(s) above)");
-        }
+        // Only show unassigned and no project buckets that have a budget or
+        // time entries
+        self.period_bucket_durations.retain(|v| {
+            !(v.bucket.is_unassigned() || v.bucket.no_project)
+                || v.durations.expected != Duration::zero()
+                || v.durations.actual != Duration::zero()
+        });
         debug!(
             "processed period_bucket_durations: {:#?}",
             self.period_bucket_durations
         );
-        Ok(())
+        Ok(self.diagnostics.clone())
+    }
+
+    pub fn period_bucket_durations(&self) -> &[PeriodBucketDurations] {
+        &self.period_bucket_durations
     }
 
     pub fn print_table(&self) {
@@ -573,17 +1533,22 @@ impl Processor {
         ]);
         for PeriodBucketDurations {
             period_start,
-            bucket: Bucket { client, project },
+            bucket,
             period_length: _,
             last_work_day_offset: _,
             durations,
+            max_expected: _,
+            day_expected: _,
+            day_actual: _,
+            rollover: _,
+            count: _,
         } in &self.period_bucket_durations
         {
             table.add_row(Row::new(vec![
                 Cell::new(&period_start.naive_local().to_string()),
                 Cell::new(""),
-                Cell::new(&client),
-                Cell::new(project.as_ref().map(String::as_str).unwrap_or("")),
+                Cell::new(bucket.client_label()),
+                Cell::new(&bucket.project_label()),
                 Cell::new(""),
                 duration_hours_cell(durations.expected),
                 // duration_hours_cell(durations.partial_expected),
@@ -598,9 +1563,195 @@ impl Processor {
         println!();
     }
 
+    pub fn print_days_table(&self) {
+        let today = self.now.date();
+        let current_period_bucket_durations: Vec<_> = self
+            .period_bucket_durations
+            .iter()
+            .filter(|v| is_date_in_period(today, v.period_start, v.period_length))
+            .collect();
+        let mut table = Table::new();
+        table.set_format(
+            prettytable::format::FormatBuilder::new()
+                .column_separator(' ')
+                .build(),
+        );
+        table.add_row(row![
+            b->"DATE",
+            " ",
+            b->"CLIENT",
+            b->"PROJECT",
+            " ",
+            br->"EXPECT",
+            br->"ACTUAL",
+            br->"CUMULATIVE",
+        ]);
+        let mut cumulative = vec![Duration::zero(); current_period_bucket_durations.len()];
+        let max_period_length = current_period_bucket_durations
+            .iter()
+            .map(|v| v.period_length)
+            .max()
+            .unwrap_or(0);
+        for offset in 0..max_period_length {
+            let mut previous_date = None;
+            for (index, source) in current_period_bucket_durations.iter().enumerate() {
+                if offset >= source.period_length {
+                    continue;
+                }
+                let date = source.period_start + Duration::days(offset);
+                let expected = source.day_expected[offset as usize];
+                let actual = source.day_actual[offset as usize];
+                cumulative[index] = cumulative[index] + actual - expected;
+                let date_cell = if previous_date == Some(date) {
+                    Cell::new("")
+                } else {
+                    Cell::new(&date.naive_local().format("%Y-%m-%d %a").to_string())
+                };
+                previous_date = Some(date);
+                let (actual_cell, cumulative_cell) = if date > today {
+                    (Cell::new(""), Cell::new(""))
+                } else {
+                    (
+                        duration_hours_cell(actual),
+                        color_duration_hours_cell(cumulative[index]),
+                    )
+                };
+                table.add_row(Row::new(vec![
+                    date_cell,
+                    Cell::new(""),
+                    Cell::new(source.bucket.client_label()),
+                    Cell::new(&source.bucket.project_label()),
+                    Cell::new(""),
+                    duration_hours_cell(expected),
+                    actual_cell,
+                    cumulative_cell,
+                ]));
+            }
+        }
+        table.printstd();
+        println!();
+    }
+
+    /// Print the expected hours allocated to each day of every period, before
+    /// any time entries are counted
+    pub fn print_allocation_table(&self) {
+        let mut table = Table::new();
+        table.set_format(
+            prettytable::format::FormatBuilder::new()
+                .column_separator(' ')
+                .build(),
+        );
+        table.add_row(row![
+            b->"PERIOD",
+            br->"DAYS",
+            " ",
+            b->"CLIENT",
+            b->"PROJECT",
+            " ",
+            br->"EXPECT",
+            br->"WORK",
+            " ",
+            b->"DAILY",
+        ]);
+        let mut previous_period_start = None;
+        for source in &self.period_bucket_durations {
+            if (source.bucket.is_unassigned() || source.bucket.no_project)
+                && source.durations.expected == Duration::zero()
+            {
+                continue;
+            }
+            let (period_cell, days_cell) = if previous_period_start == Some(source.period_start) {
+                (Cell::new(""), Cell::new(""))
+            } else {
+                (
+                    Cell::new(
+                        &source
+                            .period_start
+                            .naive_local()
+                            .format("%Y-%m-%d %a")
+                            .to_string(),
+                    ),
+                    cell!(r->source.period_length),
+                )
+            };
+            previous_period_start = Some(source.period_start);
+            let work_days = source
+                .day_expected
+                .iter()
+                .filter(|v| **v > Duration::zero())
+                .count();
+            let daily: Vec<_> = source
+                .day_expected
+                .iter()
+                .map(|v| format_duration_hours(*v))
+                .collect();
+            table.add_row(Row::new(vec![
+                period_cell,
+                days_cell,
+                Cell::new(""),
+                Cell::new(source.bucket.client_label()),
+                Cell::new(&source.bucket.project_label()),
+                Cell::new(""),
+                duration_hours_cell(source.durations.expected),
+                cell!(r->work_days),
+                Cell::new(""),
+                Cell::new(&daily.join(" ")),
+            ]));
+        }
+        table.printstd();
+    }
+
+    /// Print the time entries missing a client or project, or that no expected
+    /// client matched, added up by day, client, project and description
+    pub fn print_unmatched_table(&self) {
+        if self.unmatched.is_empty() {
+            return;
+        }
+        let mut table = Table::new();
+        table.set_format(
+            prettytable::format::FormatBuilder::new()
+                .column_separator(' ')
+                .build(),
+        );
+        table.add_row(row![
+            b->"DATE",
+            " ",
+            b->"CLIENT",
+            b->"PROJECT",
+            b->"DESCRIPTION",
+            " ",
+            br->"ACTUAL",
+        ]);
+        let mut previous_date = None;
+        for (key, duration) in &self.unmatched {
+            let date_cell = if previous_date == Some(key.date) {
+                Cell::new("")
+            } else {
+                Cell::new(&key.date.format("%Y-%m-%d %a").to_string())
+            };
+            previous_date = Some(key.date);
+            table.add_row(Row::new(vec![
+                date_cell,
+                Cell::new(""),
+                Cell::new(key.client.as_deref().unwrap_or("(no client)")),
+                Cell::new(key.project.as_deref().unwrap_or("(no project)")),
+                Cell::new(key.description.as_deref().unwrap_or("")),
+                Cell::new(""),
+                duration_hours_cell(*duration),
+            ]));
+        }
+        table.printstd();
+        println!();
+    }
+
     pub fn calculate_totals(&self) -> HashMap<Bucket, TotalDurations> {
         let mut result: HashMap<Bucket, TotalDurations> = HashMap::new();
+        let mut bucket_sources: HashMap<&Bucket, Vec<&PeriodBucketDurations>> = HashMap::new();
         for source in &self.period_bucket_durations {
+            bucket_sources
+                .entry(&source.bucket)
+                .or_insert_with(Vec::new)
+                .push(source);
             let source_end_work_date =
                 source.period_start + Duration::days(source.last_work_day_offset + 1);
             if let Some(entry) = result.get_mut(&source.bucket) {
@@ -614,11 +1765,899 @@ impl Processor {
                     TotalDurations {
                         end_work_date: source_end_work_date,
                         durations: source.durations,
+                        status: None,
+                        running: self.running.get(&source.bucket).cloned(),
+                        budgeted: true,
                     },
                 );
             }
         }
+        for (bucket, sources) in bucket_sources {
+            let (adjustment, partial_adjustment) =
+                calculate_rollover_adjustment(self.now.date(), &sources);
+            let total = result.get_mut(bucket).unwrap();
+            total.budgeted = !(bucket.is_unassigned() || bucket.no_project)
+                || sources
+                    .iter()
+                    .any(|v| v.durations.expected > Duration::zero());
+            total.durations.expected = total.durations.expected + adjustment;
+            total.durations.partial_expected =
+                total.durations.partial_expected + partial_adjustment;
+            if sources.iter().any(|v| v.max_expected.is_range()) {
+                total.status = Some(match total.durations.remaining() {
+                    v if v < Duration::zero() => RangeStatus::Under,
+                    v if v > Duration::zero() => RangeStatus::Over,
+                    _ => RangeStatus::InRange,
+                });
+            }
+        }
         debug!("total_bucket_durations: {:#?}", result);
         result
     }
 }
+
+/// Sum the client totals and budgeted unassigned totals (project and tag totals
+/// are already included in or overlap them), ending on the latest client end
+/// work date.  Unassigned time without an overhead budget is left out, since
+/// none of it is expected.
+pub fn calculate_grand_total(
+    now: DateTime<Local>,
+    total_bucket_durations: &HashMap<Bucket, TotalDurations>,
+) -> TotalDurations {
+    let mut result = TotalDurations {
+        end_work_date: now.date(),
+        durations: Durations::zero(),
+        status: None,
+        running: None,
+        budgeted: true,
+    };
+    for (bucket, total) in total_bucket_durations {
+        if bucket.is_client_total() || (bucket.is_unassigned() && total.budgeted) {
+            result.durations = result.durations + total.durations;
+            result.end_work_date = result.end_work_date.max(total.end_work_date);
+            result.running = result.running.max(total.running);
+        }
+    }
+    result
+}
+
+/// A processor initialized from YAML input
+#[cfg(test)]
+pub(crate) fn test_processor(now: DateTime<Local>, input: &str) -> Result<Processor> {
+    let mut processor = Processor::new(now);
+    processor.initialize(serde_yaml::from_str(input)?)?;
+    Ok(processor)
+}
+
+#[test]
+fn test_initialize_client_work_days() -> Result<()> {
+    let processor = test_processor(
+        Local.ymd(2021, 3, 9).and_hms(12, 0, 0),
+        "
+periods:
+- start: 2021-03-07
+  clients:
+    Shared:
+      expected-hours: 20
+    Own:
+      expected-hours: 8
+      work-days:
+        tue: 4
+        thu: 4
+      projects:
+        Project:
+          expected-hours: 2
+",
+    )?;
+    let find = |bucket: Bucket| {
+        processor
+            .period_bucket_durations()
+            .iter()
+            .find(|v| v.bucket == bucket)
+            .unwrap()
+    };
+    let hours = |values: &[i64]| {
+        values
+            .iter()
+            .map(|v| Duration::hours(*v))
+            .collect::<Vec<_>>()
+    };
+    let shared = find(client_bucket("Shared"));
+    assert_eq!(shared.day_expected, hours(&[0, 4, 4, 4, 4, 4, 0]));
+    assert_eq!(shared.last_work_day_offset, 5);
+    assert_eq!(shared.durations.partial_expected, Duration::hours(8));
+    let own = find(client_bucket("Own"));
+    assert_eq!(own.day_expected, hours(&[0, 0, 4, 0, 4, 0, 0]));
+    assert_eq!(own.last_work_day_offset, 4);
+    assert_eq!(own.durations.partial_expected, Duration::hours(4));
+    let project = find(Bucket {
+        project: Some("Project".to_string()),
+        ..client_bucket("Own")
+    });
+    assert_eq!(project.day_expected, hours(&[0, 0, 1, 0, 1, 0, 0]));
+    assert_eq!(project.last_work_day_offset, 4);
+    Ok(())
+}
+
+#[test]
+fn test_initialize_without_shared_hours() -> Result<()> {
+    let processor = test_processor(
+        Local.ymd(2021, 3, 9).and_hms(12, 0, 0),
+        "
+periods:
+- start: 2021-03-07
+  work-days:
+    mon: 6
+    tue: 2
+  clients:
+    Own:
+      expected-hours: 8
+      work-days:
+        tue: 4
+        thu: 4
+  tags:
+    meetings:
+      expected-hours: 4
+  overhead:
+    expected-hours: 8
+",
+    )?;
+    let day_expected = |bucket: Bucket| {
+        processor
+            .period_bucket_durations()
+            .iter()
+            .find(|v| v.bucket == bucket)
+            .unwrap()
+            .day_expected
+            .clone()
+    };
+    let hours = |values: &[i64]| {
+        values
+            .iter()
+            .map(|v| Duration::hours(*v))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        day_expected(client_bucket("Own")),
+        hours(&[0, 0, 4, 0, 4, 0, 0])
+    );
+    // The period's work days still apply to the tag and overhead hours
+    assert_eq!(
+        day_expected(Bucket {
+            tag: Some("meetings".to_string()),
+            ..Bucket::unassigned()
+        }),
+        hours(&[0, 3, 1, 0, 0, 0, 0])
+    );
+    assert_eq!(
+        day_expected(Bucket::unassigned()),
+        hours(&[0, 6, 2, 0, 0, 0, 0])
+    );
+    Ok(())
+}
+
+#[test]
+fn test_initialize_period_length() {
+    let result = test_processor(
+        Local.ymd(2021, 3, 9).and_hms(12, 0, 0),
+        "
+periods:
+- start: 2021-03-07
+  length: 0
+  clients:
+    Client:
+      expected-hours: 10
+",
+    );
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "Period starting 2021-03-07 must have a positive length"
+    );
+}
+
+#[test]
+fn test_process_recorded_entries() -> Result<()> {
+    let mut processor = test_processor(
+        Local.ymd(2021, 3, 10).and_hms(17, 0, 0),
+        "
+periods:
+- start: 2021-03-07
+  clients:
+    Client:
+      expected-hours: 10
+      projects:
+        Project:
+          expected-hours: 4
+",
+    )?;
+    let entry = |day, hours: u32, project| {
+        Entry::for_test(
+            Some("Client"),
+            Some(project),
+            Local.ymd(2021, 3, day).and_hms(9, 0, 0),
+            Some(Local.ymd(2021, 3, day).and_hms(9 + hours, 0, 0)),
+        )
+    };
+    let entries = vec![
+        entry(8, 2, "Project"),
+        entry(9, 3, "Other"),
+        entry(10, 1, "Project"),
+    ];
+    assert_eq!(processor.process(&entries)?, Vec::new());
+    let totals = processor.calculate_totals();
+    let client_totals = &totals[&client_bucket("Client")];
+    assert_eq!(client_totals.durations.actual, Duration::hours(6));
+    assert_eq!(client_totals.durations.today_actual, Duration::hours(1));
+    let project_totals = &totals[&Bucket {
+        project: Some("Project".to_string()),
+        ..client_bucket("Client")
+    }];
+    assert_eq!(project_totals.durations.actual, Duration::hours(3));
+    Ok(())
+}
+
+#[test]
+fn test_process_day_breakdown() -> Result<()> {
+    let mut processor = test_processor(
+        Local.ymd(2021, 3, 10).and_hms(17, 0, 0),
+        "
+periods:
+- start: 2021-03-07
+  clients:
+    Client:
+      expected-hours: 10
+",
+    )?;
+    let entry = |start, stop| Entry::for_test(Some("Client"), Some("Project"), start, Some(stop));
+    let entries = vec![
+        entry(
+            Local.ymd(2021, 3, 8).and_hms(9, 0, 0),
+            Local.ymd(2021, 3, 8).and_hms(11, 0, 0),
+        ),
+        // Split at midnight between the two days
+        entry(
+            Local.ymd(2021, 3, 9).and_hms(23, 0, 0),
+            Local.ymd(2021, 3, 10).and_hms(1, 30, 0),
+        ),
+    ];
+    processor.process(&entries)?;
+    let client = processor
+        .period_bucket_durations()
+        .iter()
+        .find(|v| v.bucket.is_client_total())
+        .unwrap();
+    let minutes = |values: &[i64]| {
+        values
+            .iter()
+            .map(|v| Duration::minutes(*v))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        client.day_expected,
+        minutes(&[0, 120, 120, 120, 120, 120, 0])
+    );
+    assert_eq!(client.day_actual, minutes(&[0, 120, 60, 90, 0, 0, 0]));
+    assert_eq!(client.durations.today_actual, Duration::minutes(90));
+    Ok(())
+}
+
+#[test]
+fn test_process_tag_entries() -> Result<()> {
+    let mut processor = test_processor(
+        Local.ymd(2021, 3, 10).and_hms(17, 0, 0),
+        "
+periods:
+- start: 2021-03-07
+  clients:
+    Client:
+      expected-hours: 10
+      tags:
+        support:
+          expected-hours: 2
+  tags:
+    meetings:
+      expected-hours: 3
+",
+    )?;
+    let entry = |day, client: Option<&str>, tags: &[&str]| Entry {
+        tags: tags.iter().map(|v| v.to_string()).collect(),
+        ..Entry::for_test(
+            client,
+            client.map(|_| "Project"),
+            Local.ymd(2021, 3, day).and_hms(9, 0, 0),
+            Some(Local.ymd(2021, 3, day).and_hms(10, 0, 0)),
+        )
+    };
+    let entries = vec![
+        entry(8, Some("Client"), &["support", "meetings"]),
+        entry(9, Some("Client"), &["support"]),
+        entry(9, None, &["meetings"]),
+    ];
+    processor.process(&entries)?;
+    let totals = processor.calculate_totals();
+    let actual = |bucket: Bucket| totals[&bucket].durations.actual;
+    let tag_bucket = |bucket: Bucket, tag: &str| Bucket {
+        tag: Some(tag.to_string()),
+        ..bucket
+    };
+    assert_eq!(actual(client_bucket("Client")), Duration::hours(2));
+    assert_eq!(
+        actual(tag_bucket(client_bucket("Client"), "support")),
+        Duration::hours(2)
+    );
+    assert_eq!(
+        actual(tag_bucket(Bucket::unassigned(), "meetings")),
+        Duration::hours(2)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_process_unassigned_entries() -> Result<()> {
+    let mut processor = test_processor(
+        Local.ymd(2021, 3, 10).and_hms(17, 0, 0),
+        "
+periods:
+- start: 2021-02-28
+  clients:
+    Client:
+      expected-hours: 10
+- start: 2021-03-07
+  clients:
+    Client:
+      expected-hours: 10
+  overhead:
+    expected-hours: 3
+",
+    )?;
+    let entry = |day, client, project| {
+        Entry::for_test(
+            client,
+            project,
+            Local.ymd(2021, 3, day).and_hms(9, 0, 0),
+            Some(Local.ymd(2021, 3, day).and_hms(10, 0, 0)),
+        )
+    };
+    let entries = vec![
+        entry(8, Some("Client"), Some("Project")),
+        entry(8, None, None),
+        entry(9, Some("Client"), None),
+        entry(9, Some("Other"), Some("Project")),
+        entry(10, Some("Other"), Some("Project")),
+    ];
+    // Besides the entries overlapping on each day, only the entries for a
+    // client that is not expected are reported
+    assert_eq!(
+        processor
+            .process(&entries)?
+            .into_iter()
+            .map(|v| v.kind)
+            .filter(|v| !matches!(v, DiagnosticKind::OverlappingEntries { .. }))
+            .collect::<Vec<_>>(),
+        vec![DiagnosticKind::UnmatchedEntry; 2]
+    );
+    let period_starts = |bucket| {
+        processor
+            .period_bucket_durations()
+            .iter()
+            .filter(|v| v.bucket == bucket)
+            .map(|v| v.period_start)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        period_starts(Bucket::unassigned()),
+        vec![Local.ymd(2021, 3, 7)]
+    );
+    assert_eq!(
+        period_starts(Bucket::no_project("Client")),
+        vec![Local.ymd(2021, 3, 7)]
+    );
+    let totals = processor.calculate_totals();
+    assert!(totals[&Bucket::unassigned()].budgeted);
+    let unassigned = totals[&Bucket::unassigned()].durations;
+    assert_eq!(unassigned.expected, Duration::hours(3));
+    assert_eq!(unassigned.actual, Duration::hours(1));
+    // The entry without a project counts toward its client's (no project)
+    // bucket instead of the client
+    let no_project = &totals[&Bucket::no_project("Client")];
+    assert!(!no_project.budgeted);
+    assert_eq!(no_project.durations.actual, Duration::hours(1));
+    let client = totals[&client_bucket("Client")].durations;
+    assert_eq!(client.actual, Duration::hours(1));
+    let grand_total = calculate_grand_total(processor.now, &totals).durations;
+    assert_eq!(grand_total.expected, Duration::hours(23));
+    assert_eq!(grand_total.actual, Duration::hours(2));
+    let unmatched_key = |day, client: Option<&str>, project: Option<&str>| UnmatchedKey {
+        date: NaiveDate::from_ymd(2021, 3, day),
+        client: client.map(String::from),
+        project: project.map(String::from),
+        description: None,
+    };
+    assert_eq!(
+        processor.unmatched.into_iter().collect::<Vec<_>>(),
+        vec![
+            (
+                unmatched_key(9, Some("Other"), Some("Project")),
+                Duration::hours(1)
+            ),
+            (
+                unmatched_key(10, Some("Other"), Some("Project")),
+                Duration::hours(1)
+            ),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_process_unbudgeted_unassigned_entries() -> Result<()> {
+    let mut processor = test_processor(
+        Local.ymd(2021, 3, 8).and_hms(17, 0, 0),
+        "
+periods:
+- start: 2021-03-07
+  clients:
+    Client:
+      expected-hours: 10
+",
+    )?;
+    let entry = |client, project| {
+        Entry::for_test(
+            client,
+            project,
+            Local.ymd(2021, 3, 8).and_hms(9, 0, 0),
+            Some(Local.ymd(2021, 3, 8).and_hms(10, 0, 0)),
+        )
+    };
+    let entries = vec![entry(Some("Client"), Some("Project")), entry(None, None)];
+    processor.process(&entries)?;
+    let totals = processor.calculate_totals();
+    let unassigned = &totals[&Bucket::unassigned()];
+    assert!(!unassigned.budgeted);
+    assert_eq!(unassigned.durations.actual, Duration::hours(1));
+    assert_eq!(unassigned.daily_average_remaining(processor.now), None);
+    let client = &totals[&client_bucket("Client")];
+    let grand_total = calculate_grand_total(processor.now, &totals);
+    assert_eq!(grand_total.durations, client.durations);
+    assert_eq!(
+        grand_total.daily_average_remaining(processor.now),
+        client.daily_average_remaining(processor.now)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_process_diagnostics() -> Result<()> {
+    let mut processor = test_processor(
+        Local.ymd(2021, 3, 16).and_hms(12, 0, 0),
+        "
+periods:
+- start: 2021-03-01
+  clients:
+    Client:
+      expected-hours: 10
+- start: 2021-03-15
+  clients:
+    Client:
+      expected-hours: 10
+",
+    )?;
+    processor.set_long_running_threshold(Some(Duration::hours(2)));
+    let time = |day, hour| Local.ymd(2021, 3, day).and_hms(hour, 0, 0);
+    let entry = |id, start, stop, client, project| Entry {
+        id: Some(id),
+        ..Entry::for_test(client, project, start, stop)
+    };
+    let entries = vec![
+        entry(
+            1,
+            time(2, 22),
+            Some(time(3, 2)),
+            Some("Other"),
+            Some("Project"),
+        ),
+        entry(
+            2,
+            time(9, 9),
+            Some(time(9, 10)),
+            Some("Client"),
+            Some("Project"),
+        ),
+        entry(3, time(15, 9), Some(time(15, 10)), Some("Other"), None),
+        entry(4, time(16, 9), None, Some("Client"), Some("Project")),
+        entry(5, time(16, 11), None, Some("Client"), Some("Project")),
+    ];
+    let diagnostic = |kind, entry_id, start: DateTime<Local>, hours| Diagnostic {
+        kind,
+        entry_id: Some(entry_id),
+        start: start.with_timezone(&Utc),
+        duration: Duration::hours(hours),
+    };
+    assert_eq!(
+        processor.process(&entries)?,
+        vec![
+            diagnostic(DiagnosticKind::UnmatchedEntry, 1, time(2, 22), 4),
+            diagnostic(DiagnosticKind::OutsidePeriods, 2, time(9, 9), 1),
+            diagnostic(DiagnosticKind::MissingProject, 3, time(15, 9), 1),
+            diagnostic(
+                DiagnosticKind::LongRunningEntry {
+                    threshold: Duration::hours(2)
+                },
+                4,
+                time(16, 9),
+                3
+            ),
+            diagnostic(
+                DiagnosticKind::OverlappingEntries {
+                    other_entry_id: Some(4),
+                    overlap_start: time(16, 11).with_timezone(&Utc),
+                    overlap_stop: time(16, 12).with_timezone(&Utc),
+                },
+                5,
+                time(16, 11),
+                1
+            ),
+            diagnostic(DiagnosticKind::RunningEntry, 5, time(16, 11), 1),
+        ]
+    );
+    let totals = processor.calculate_totals();
+    assert_eq!(
+        totals[&client_bucket("Client")].running,
+        Some(Duration::hours(3))
+    );
+    Ok(())
+}
+
+#[test]
+fn test_process_running_entry() -> Result<()> {
+    let input = "
+periods:
+- start: 2021-03-07
+  clients:
+    Client:
+      expected-hours: 10
+";
+    let entries = vec![Entry {
+        id: Some(1),
+        ..Entry::for_test(
+            Some("Client"),
+            Some("Project"),
+            Local.ymd(2021, 3, 8).and_hms(9, 0, 0),
+            None,
+        )
+    }];
+    let process = |threshold| -> Result<(Vec<Diagnostic>, TotalDurations)> {
+        let mut processor = test_processor(Local.ymd(2021, 3, 8).and_hms(12, 30, 0), input)?;
+        processor.set_long_running_threshold(threshold);
+        let diagnostics = processor.process(&entries)?;
+        let total = processor.calculate_totals()[&client_bucket("Client")].clone();
+        Ok((diagnostics, total))
+    };
+    let running = Duration::minutes(210);
+    // Counted up to the injected now
+    let (diagnostics, total) = process(None)?;
+    assert_eq!(total.durations.actual, running);
+    assert_eq!(total.durations.today_actual, running);
+    assert_eq!(total.running, Some(running));
+    let kinds = |diagnostics: Vec<Diagnostic>| {
+        diagnostics
+            .into_iter()
+            .map(|v| (v.kind, v.duration))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        kinds(diagnostics),
+        vec![(DiagnosticKind::RunningEntry, running)]
+    );
+    // Only running for longer than the threshold is a warning
+    assert_eq!(
+        kinds(process(Some(running))?.0),
+        vec![(DiagnosticKind::RunningEntry, running)]
+    );
+    let threshold = running - Duration::minutes(1);
+    assert_eq!(
+        kinds(process(Some(threshold))?.0),
+        vec![(DiagnosticKind::LongRunningEntry { threshold }, running)]
+    );
+    Ok(())
+}
+
+/// A time on 2021-03-08, for the overlapping time entry tests
+#[cfg(test)]
+fn overlap_time((hour, min): (u32, u32)) -> DateTime<Local> {
+    Local.ymd(2021, 3, 8).and_hms(hour, min, 0)
+}
+
+#[cfg(test)]
+fn overlapping_entry(id: i64, start: (u32, u32), stop: (u32, u32)) -> Entry {
+    Entry {
+        id: Some(id),
+        ..Entry::for_test(
+            Some("Client"),
+            Some("Project"),
+            overlap_time(start),
+            Some(overlap_time(stop)),
+        )
+    }
+}
+
+#[cfg(test)]
+fn overlap(id: i64, other_id: i64, start: (u32, u32), stop: (u32, u32)) -> Diagnostic {
+    let (start, stop) = (overlap_time(start), overlap_time(stop));
+    Diagnostic {
+        kind: DiagnosticKind::OverlappingEntries {
+            other_entry_id: Some(other_id),
+            overlap_start: start.with_timezone(&Utc),
+            overlap_stop: stop.with_timezone(&Utc),
+        },
+        entry_id: Some(id),
+        start: start.with_timezone(&Utc),
+        duration: stop - start,
+    }
+}
+
+/// Process time entries, returning the diagnostics and the client's actual
+/// hours
+#[cfg(test)]
+fn process_overlapping_entries(
+    count_overlaps_once: bool,
+    entries: Vec<Entry>,
+) -> Result<(Vec<Diagnostic>, Duration)> {
+    let mut processor = test_processor(
+        Local.ymd(2021, 3, 10).and_hms(17, 0, 0),
+        "
+periods:
+- start: 2021-03-07
+  clients:
+    Client:
+      expected-hours: 10
+",
+    )?;
+    processor.set_count_overlaps_once(count_overlaps_once);
+    let diagnostics = processor.process(&entries)?;
+    let actual = processor.calculate_totals()[&client_bucket("Client")]
+        .durations
+        .actual;
+    Ok((diagnostics, actual))
+}
+
+#[test]
+fn test_process_overlapping_entries() -> Result<()> {
+    let entries = vec![
+        overlapping_entry(1, (9, 0), (12, 0)),
+        overlapping_entry(2, (11, 0), (13, 0)),
+        overlapping_entry(3, (9, 30), (10, 0)),
+    ];
+    let diagnostics = vec![
+        overlap(3, 1, (9, 30), (10, 0)),
+        overlap(2, 1, (11, 0), (12, 0)),
+    ];
+    assert_eq!(
+        process_overlapping_entries(false, entries.clone())?,
+        (diagnostics.clone(), Duration::minutes(330))
+    );
+    assert_eq!(
+        process_overlapping_entries(true, entries)?,
+        (diagnostics, Duration::hours(4))
+    );
+    Ok(())
+}
+
+#[test]
+fn test_process_mutually_overlapping_entries() -> Result<()> {
+    let entries = vec![
+        overlapping_entry(1, (9, 0), (12, 0)),
+        overlapping_entry(2, (10, 0), (12, 30)),
+        overlapping_entry(3, (11, 0), (13, 0)),
+    ];
+    // The last entry overlaps both earlier entries
+    let diagnostics = vec![
+        overlap(2, 1, (10, 0), (12, 0)),
+        overlap(3, 1, (11, 0), (12, 0)),
+        overlap(3, 2, (11, 0), (12, 30)),
+    ];
+    assert_eq!(
+        process_overlapping_entries(false, entries.clone())?,
+        (diagnostics.clone(), Duration::minutes(450))
+    );
+    assert_eq!(
+        process_overlapping_entries(true, entries)?,
+        (diagnostics, Duration::hours(4))
+    );
+    Ok(())
+}
+
+#[test]
+fn test_process_aliases() -> Result<()> {
+    let mut processor = test_processor(
+        Local.ymd(2021, 3, 10).and_hms(17, 0, 0),
+        "
+periods:
+- start: 2021-03-07
+  clients:
+    ACME:
+      expected-hours: 10
+      aliases: [\"glob:ACME Corp*\"]
+      projects:
+        Website:
+          expected-hours: 4
+          aliases: [\"/Web ?site/\"]
+    \"/ACME Corp.*/\":
+      expected-hours: 5
+",
+    )?;
+    let entry = |client, project| {
+        Entry::for_test(
+            Some(client),
+            Some(project),
+            Local.ymd(2021, 3, 8).and_hms(9, 0, 0),
+            Some(Local.ymd(2021, 3, 8).and_hms(10, 0, 0)),
+        )
+    };
+    let entries = vec![entry("ACME", "Web site")];
+    assert_eq!(processor.process(&entries)?, Vec::new());
+    let totals = processor.calculate_totals();
+    let project_totals = &totals[&Bucket {
+        project: Some("Website".to_string()),
+        ..client_bucket("ACME")
+    }];
+    assert_eq!(project_totals.durations.actual, Duration::hours(1));
+    let ambiguous_entries = vec![entry("ACME Corp.", "Website")];
+    assert!(processor.process(&ambiguous_entries).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_process_billable_count() -> Result<()> {
+    let mut processor = test_processor(
+        Local.ymd(2021, 3, 10).and_hms(17, 0, 0),
+        "
+periods:
+- start: 2021-03-07
+  clients:
+    Client:
+      expected-hours: 10
+      count: billable
+      projects:
+        Project:
+          expected-hours: 4
+          count: all
+",
+    )?;
+    let entry = |day, billable| Entry {
+        billable,
+        ..Entry::for_test(
+            Some("Client"),
+            Some("Project"),
+            Local.ymd(2021, 3, day).and_hms(9, 0, 0),
+            Some(Local.ymd(2021, 3, day).and_hms(10, 0, 0)),
+        )
+    };
+    let entries = vec![entry(8, true), entry(9, false), entry(10, false)];
+    assert_eq!(processor.process(&entries)?, Vec::new());
+    let totals = processor.calculate_totals();
+    let client = totals[&client_bucket("Client")].durations;
+    assert_eq!(client.actual, Duration::hours(1));
+    assert_eq!(client.today_actual, Duration::zero());
+    assert_eq!(client.current_period_billable_actual, Duration::hours(1));
+    assert_eq!(
+        client.current_period_non_billable_actual,
+        Duration::hours(2)
+    );
+    let project = totals[&Bucket {
+        project: Some("Project".to_string()),
+        ..client_bucket("Client")
+    }]
+        .durations;
+    assert_eq!(project.actual, Duration::hours(3));
+    Ok(())
+}
+
+#[test]
+fn test_process_hours_range() -> Result<()> {
+    let input = "
+periods:
+- start: 2021-02-28
+  clients:
+    Client:
+      min-hours: 10
+      max-hours: 15
+- start: 2021-03-07
+  clients:
+    Client:
+      min-hours: 10
+      max-hours: 15
+";
+    let entry = |day, hours: u32| {
+        Entry::for_test(
+            Some("Client"),
+            Some("Project"),
+            Local.ymd(2021, 3, day).and_hms(6, 0, 0),
+            Some(Local.ymd(2021, 3, day).and_hms(6 + hours, 0, 0)),
+        )
+    };
+    let client = client_bucket("Client");
+    let totals = |input, entries: Vec<Entry>| -> Result<(Processor, TotalDurations)> {
+        let mut processor = test_processor(Local.ymd(2021, 3, 12).and_hms(17, 0, 0), input)?;
+        assert_eq!(processor.process(&entries)?, Vec::new());
+        let total = processor.calculate_totals()[&client].clone();
+        Ok((processor, total))
+    };
+    let status = |entries| -> Result<Option<RangeStatus>> { Ok(totals(input, entries)?.1.status) };
+    // The first period's 12 hours are in range, so nothing rolls over
+    assert_eq!(
+        status(vec![entry(1, 12), entry(8, 9)])?,
+        Some(RangeStatus::Under)
+    );
+    assert_eq!(
+        status(vec![entry(1, 12), entry(8, 12)])?,
+        Some(RangeStatus::InRange)
+    );
+    assert_eq!(
+        status(vec![entry(1, 12), entry(8, 16)])?,
+        Some(RangeStatus::Over)
+    );
+    // The first period's hour over the maximum rolls over
+    assert_eq!(
+        status(vec![entry(1, 16), entry(8, 15)])?,
+        Some(RangeStatus::Over)
+    );
+    // Only the ended period's hours within its range move today's expected
+    // hours
+    let (processor, total) = totals(input, vec![entry(1, 12), entry(8, 12)])?;
+    let partial_expected = processor
+        .period_bucket_durations()
+        .iter()
+        .filter(|v| v.bucket == client)
+        .fold(Duration::zero(), |acc, v| {
+            acc + v.durations.partial_expected
+        });
+    assert_eq!(
+        total.durations.partial_expected,
+        partial_expected + Duration::hours(2)
+    );
+    // A range with only a minimum has no upper bound
+    let min_input = "
+periods:
+- start: 2021-03-07
+  clients:
+    Client:
+      min-hours: 10
+";
+    let min_status =
+        |entries| -> Result<Option<RangeStatus>> { Ok(totals(min_input, entries)?.1.status) };
+    assert_eq!(min_status(vec![entry(8, 9)])?, Some(RangeStatus::Under));
+    assert_eq!(min_status(vec![entry(8, 16)])?, Some(RangeStatus::InRange));
+    Ok(())
+}
+
+#[test]
+fn test_initialize_project_percent() -> Result<()> {
+    let input = |percent| {
+        format!(
+            "
+periods:
+- start: 2021-03-07
+  clients:
+    Client:
+      expected-hours: 20
+      projects:
+        First:
+          expected-percent: {}
+        Second:
+          expected-percent: 30
+",
+            percent
+        )
+    };
+    let now = Local.ymd(2021, 3, 9).and_hms(12, 0, 0);
+    let processor = test_processor(now, &input(25))?;
+    let first = processor
+        .period_bucket_durations()
+        .iter()
+        .find(|v| v.bucket.project.as_deref() == Some("First"))
+        .unwrap();
+    assert_eq!(first.durations.expected, Duration::hours(5));
+    assert!(test_processor(now, &input(80)).is_err());
+    Ok(())
+}
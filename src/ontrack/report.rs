@@ -0,0 +1,276 @@
+use crate::ontrack::processor::*;
+use crate::ontrack::types::*;
+use chrono::prelude::*;
+use chrono::Duration;
+use serde::Serialize;
+use std::collections::HashMap;
+
+fn duration_hours(duration: Duration) -> f64 {
+    duration.num_seconds() as f64 / 3600.0
+}
+
+/// Durations in decimal hours
+#[derive(Clone, Debug, Serialize)]
+pub struct DurationsReport {
+    pub expected: f64,
+    pub partial_expected: f64,
+    pub actual: f64,
+    pub today_actual: f64,
+    pub current_period_actual: f64,
+    pub current_period_billable_actual: f64,
+    pub current_period_non_billable_actual: f64,
+    pub current_period_expected: f64,
+    pub today_expected: f64,
+    pub remaining: f64,
+    pub partial_remaining: f64,
+}
+
+impl From<&Durations> for DurationsReport {
+    fn from(durations: &Durations) -> DurationsReport {
+        DurationsReport {
+            expected: duration_hours(durations.expected),
+            partial_expected: duration_hours(durations.partial_expected),
+            actual: duration_hours(durations.actual),
+            today_actual: duration_hours(durations.today_actual),
+            current_period_actual: duration_hours(durations.current_period_actual),
+            current_period_billable_actual: duration_hours(
+                durations.current_period_billable_actual,
+            ),
+            current_period_non_billable_actual: duration_hours(
+                durations.current_period_non_billable_actual,
+            ),
+            current_period_expected: duration_hours(durations.current_period_expected()),
+            today_expected: duration_hours(durations.today_expected()),
+            remaining: duration_hours(durations.remaining()),
+            partial_remaining: duration_hours(durations.partial_remaining()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TotalReport {
+    #[serde(flatten)]
+    pub durations: DurationsReport,
+    pub daily_average_remaining: Option<f64>,
+    pub end_work_date: NaiveDate,
+    pub status: Option<RangeStatus>,
+    pub running: Option<f64>,
+}
+
+impl TotalReport {
+    fn new(now: DateTime<Local>, total: &TotalDurations) -> TotalReport {
+        TotalReport {
+            durations: DurationsReport::from(&total.durations),
+            daily_average_remaining: total.daily_average_remaining(now).map(duration_hours),
+            end_work_date: total.end_work_date.naive_local(),
+            status: total.status,
+            running: total.running.map(duration_hours),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BucketTotalReport {
+    pub client: Option<String>,
+    pub project: Option<String>,
+    pub tag: Option<String>,
+    pub no_project: bool,
+    #[serde(flatten)]
+    pub total: TotalReport,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PeriodReport {
+    pub period_start: NaiveDate,
+    pub period_length: i64,
+    pub client: Option<String>,
+    pub project: Option<String>,
+    pub tag: Option<String>,
+    pub no_project: bool,
+    #[serde(flatten)]
+    pub durations: DurationsReport,
+}
+
+/// Per-period row for spreadsheets, with durations in decimal hours rounded to
+/// two places
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PeriodCsvRecord {
+    pub period_start: NaiveDate,
+    pub period_length: i64,
+    pub client: Option<String>,
+    pub project: Option<String>,
+    pub tag: Option<String>,
+    pub no_project: bool,
+    pub expected: f64,
+    pub actual: f64,
+    pub difference: f64,
+}
+
+fn rounded_duration_hours(duration: Duration) -> f64 {
+    (duration_hours(duration) * 100.0).round() / 100.0
+}
+
+impl From<&PeriodBucketDurations> for PeriodCsvRecord {
+    fn from(source: &PeriodBucketDurations) -> PeriodCsvRecord {
+        PeriodCsvRecord {
+            period_start: source.period_start.naive_local(),
+            period_length: source.period_length,
+            client: source.bucket.client.clone(),
+            project: source.bucket.project.clone(),
+            tag: source.bucket.tag.clone(),
+            no_project: source.bucket.no_project,
+            expected: rounded_duration_hours(source.durations.expected),
+            actual: rounded_duration_hours(source.durations.actual),
+            difference: rounded_duration_hours(source.durations.remaining()),
+        }
+    }
+}
+
+#[test]
+fn test_period_csv_record() {
+    let mut durations = Durations::expected(10.0, 0.5);
+    durations.actual = Duration::minutes(200);
+    assert_eq!(
+        PeriodCsvRecord::from(&PeriodBucketDurations {
+            period_start: Local.ymd(2021, 3, 7),
+            bucket: Bucket {
+                tag: Some("support".to_string()),
+                ..client_bucket("Client")
+            },
+            period_length: 7,
+            last_work_day_offset: 5,
+            durations,
+            max_expected: MaxExpected::Exact,
+            day_expected: Vec::new(),
+            day_actual: Vec::new(),
+            rollover: Rollover::Full,
+            count: CountInput::All,
+        }),
+        PeriodCsvRecord {
+            period_start: NaiveDate::from_ymd(2021, 3, 7),
+            period_length: 7,
+            client: Some("Client".to_string()),
+            project: None,
+            tag: Some("support".to_string()),
+            no_project: false,
+            expected: 10.0,
+            actual: 3.33,
+            difference: -6.67,
+        }
+    );
+}
+
+/// Machine-readable version of the totals and per-period tables
+#[derive(Clone, Debug, Serialize)]
+pub struct Report {
+    pub now: DateTime<Local>,
+    pub buckets: Vec<BucketTotalReport>,
+    pub total: TotalReport,
+    pub periods: Vec<PeriodReport>,
+}
+
+impl Report {
+    pub fn new(
+        now: DateTime<Local>,
+        processor: &Processor,
+        total_bucket_durations: &HashMap<Bucket, TotalDurations>,
+    ) -> Report {
+        let mut sorted_buckets: Vec<_> = total_bucket_durations.keys().collect();
+        sorted_buckets.sort();
+        Report {
+            now,
+            buckets: sorted_buckets
+                .into_iter()
+                .map(|bucket| BucketTotalReport {
+                    client: bucket.client.clone(),
+                    project: bucket.project.clone(),
+                    tag: bucket.tag.clone(),
+                    no_project: bucket.no_project,
+                    total: TotalReport::new(now, &total_bucket_durations[bucket]),
+                })
+                .collect(),
+            total: TotalReport::new(now, &calculate_grand_total(now, total_bucket_durations)),
+            periods: processor
+                .period_bucket_durations()
+                .iter()
+                .map(|v| PeriodReport {
+                    period_start: v.period_start.naive_local(),
+                    period_length: v.period_length,
+                    client: v.bucket.client.clone(),
+                    project: v.bucket.project.clone(),
+                    tag: v.bucket.tag.clone(),
+                    no_project: v.bucket.no_project,
+                    durations: DurationsReport::from(&v.durations),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[test]
+fn test_report() -> anyhow::Result<()> {
+    let now = Local.ymd(2021, 3, 10).and_hms(17, 0, 0);
+    let mut processor = test_processor(
+        now,
+        "
+periods:
+- start: 2021-03-07
+  clients:
+    Client:
+      expected-hours: 10
+",
+    )?;
+    let entry = |day, stop_hour, stop_minute| {
+        Entry::for_test(
+            Some("Client"),
+            Some("Project"),
+            Local.ymd(2021, 3, day).and_hms(9, 0, 0),
+            Some(Local.ymd(2021, 3, day).and_hms(stop_hour, stop_minute, 0)),
+        )
+    };
+    processor.process(&vec![entry(8, 11, 30), entry(10, 10, 0)])?;
+    let report = serde_json::to_value(Report::new(now, &processor, &processor.calculate_totals()))?;
+    // Durations are decimal hours, and the end work date is exclusive
+    let durations = serde_json::json!({
+        "expected": 10.0,
+        "partial_expected": 6.0,
+        "actual": 3.5,
+        "today_actual": 1.0,
+        "current_period_actual": 3.5,
+        "current_period_billable_actual": 0.0,
+        "current_period_non_billable_actual": 3.5,
+        "current_period_expected": 10.0,
+        "today_expected": 3.5,
+        "remaining": -6.5,
+        "partial_remaining": -2.5,
+    });
+    let extend = |mut value: serde_json::Value, fields: serde_json::Value| {
+        let fields = fields.as_object().unwrap().clone();
+        value.as_object_mut().unwrap().extend(fields);
+        value
+    };
+    let total = extend(
+        durations.clone(),
+        serde_json::json!({
+            "daily_average_remaining": 3.25,
+            "end_work_date": "2021-03-13",
+            "status": null,
+            "running": null,
+        }),
+    );
+    let bucket =
+        serde_json::json!({"client": "Client", "project": null, "tag": null, "no_project": false});
+    assert_eq!(report["total"], total);
+    assert_eq!(
+        report["buckets"],
+        serde_json::json!([extend(total.clone(), bucket.clone())])
+    );
+    assert_eq!(
+        report["periods"],
+        serde_json::json!([extend(
+            extend(durations, bucket),
+            serde_json::json!({"period_start": "2021-03-07", "period_length": 7}),
+        )])
+    );
+    Ok(())
+}
@@ -0,0 +1,451 @@
+use crate::ontrack::types::*;
+use anyhow::*;
+use chrono::prelude::*;
+use log::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::PathBuf;
+use toggl_rs::{TimeEntry, Toggl, TogglExt};
+
+/// Provides the time entries that are accumulated by the `Processor`
+pub trait TimeEntrySource {
+    /// Get all time entries that started between `start` and `end` (inclusive)
+    fn fetch_time_entries(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<Entry>>;
+
+    /// Key identifying this source's entries in the on-disk cache, or `None` if
+    /// they should not be cached
+    fn cache_key(&self) -> Option<String> {
+        None
+    }
+}
+
+impl From<&TimeEntry> for Entry {
+    fn from(time_entry: &TimeEntry) -> Entry {
+        Entry {
+            id: Some(time_entry.id),
+            start: time_entry.start.with_timezone(&Utc),
+            stop: time_entry.stop.map(|v| v.with_timezone(&Utc)),
+            description: time_entry.description.clone(),
+            client: time_entry.client.as_ref().map(|v| v.name.clone()),
+            project: time_entry.project.as_ref().map(|v| v.name.clone()),
+            tags: time_entry.tags.clone().unwrap_or_default(),
+            billable: time_entry.billable,
+        }
+    }
+}
+
+impl TimeEntrySource for Toggl {
+    fn fetch_time_entries(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<Entry>> {
+        let time_entries = self
+            .get_time_entries_range(Some(start), Some(end))
+            .context("Could not get time entries from Toggl")?;
+        debug!("toggl time_entries: {:#?}", time_entries);
+        Ok(time_entries.iter().map(Entry::from).collect())
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        Some(format!("workspace-{}", self.user.default_wid))
+    }
+}
+
+impl TimeEntrySource for Vec<Entry> {
+    fn fetch_time_entries(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<Entry>> {
+        Ok(self
+            .iter()
+            .filter(|v| v.start >= start && v.start <= end)
+            .cloned()
+            .collect())
+    }
+}
+
+#[test]
+fn test_vec_fetch_time_entries() -> Result<()> {
+    let entry = |day| Entry {
+        id: Some(day as i64),
+        ..Entry::for_test(
+            None,
+            None,
+            Utc.ymd(2021, 3, day)
+                .and_hms(12, 0, 0)
+                .with_timezone(&Local),
+            None,
+        )
+    };
+    let entries = vec![entry(6), entry(7), entry(8), entry(9)];
+    assert_eq!(
+        entries.fetch_time_entries(
+            Utc.ymd(2021, 3, 7).and_hms(0, 0, 0),
+            Utc.ymd(2021, 3, 8).and_hms(12, 0, 0)
+        )?,
+        vec![entry(7), entry(8)]
+    );
+    Ok(())
+}
+
+/// Time entry in the JSON shape returned by the Toggl v8 or v9 time entries
+/// API.  Client and project names are taken from `client_name`/`project_name`
+/// (v9 with `meta=true`) or `client`/`project`, or else looked up from the
+/// project id (`pid` in v8, `project_id` in v9).
+#[derive(Debug, Deserialize)]
+struct JsonTimeEntry {
+    id: Option<i64>,
+    start: DateTime<Utc>,
+    #[serde(alias = "end")]
+    stop: Option<DateTime<Utc>>,
+    description: Option<String>,
+    #[serde(alias = "client_name")]
+    client: Option<String>,
+    #[serde(alias = "project_name")]
+    project: Option<String>,
+    #[serde(alias = "project_id")]
+    pid: Option<i64>,
+    tags: Option<Vec<String>>,
+    #[serde(default)]
+    billable: bool,
+}
+
+/// Project in the shape returned by the Toggl workspace projects API (`cid` in
+/// v8, `client_id` in v9)
+#[derive(Debug, Deserialize)]
+struct JsonProject {
+    id: i64,
+    name: String,
+    #[serde(alias = "client_id")]
+    cid: Option<i64>,
+}
+
+/// Client in the shape returned by the Toggl workspace clients API
+#[derive(Debug, Deserialize)]
+struct JsonClient {
+    id: i64,
+    name: String,
+}
+
+/// Time entries together with the workspace's projects and clients, used to
+/// look up the names of time entries that only have a project id
+#[derive(Debug, Deserialize)]
+struct JsonExport {
+    time_entries: Vec<JsonTimeEntry>,
+    #[serde(default)]
+    projects: Vec<JsonProject>,
+    #[serde(default)]
+    clients: Vec<JsonClient>,
+}
+
+fn parse_json_entries<R: Read>(reader: R) -> Result<Vec<Entry>> {
+    let value: serde_json::Value = serde_json::from_reader(reader)?;
+    let export = if value.is_array() {
+        JsonExport {
+            time_entries: serde_json::from_value(value)?,
+            projects: Vec::new(),
+            clients: Vec::new(),
+        }
+    } else {
+        serde_json::from_value(value)?
+    };
+    let projects: HashMap<i64, &JsonProject> = export.projects.iter().map(|v| (v.id, v)).collect();
+    let clients: HashMap<i64, &str> = export
+        .clients
+        .iter()
+        .map(|v| (v.id, v.name.as_str()))
+        .collect();
+    let mut entries = Vec::new();
+    for v in export.time_entries {
+        let id = || {
+            v.id.map_or_else(|| "without id".to_string(), |v| v.to_string())
+        };
+        if let Some(stop) = v.stop {
+            if stop < v.start {
+                bail!("Time entry {} stops before it starts", id());
+            }
+        }
+        let (mut client, mut project) = (v.client.clone(), v.project.clone());
+        if let (Some(pid), None) = (v.pid, &project) {
+            let json_project = projects.get(&pid).ok_or_else(|| {
+                anyhow!(
+                    "Time entry {} has project id {}, which is not in the export's projects",
+                    id(),
+                    pid
+                )
+            })?;
+            project = Some(json_project.name.clone());
+            if let (Some(cid), None) = (json_project.cid, &client) {
+                let name = clients.get(&cid).ok_or_else(|| {
+                    anyhow!(
+                        "Project {} has client id {}, which is not in the export's clients",
+                        json_project.name,
+                        cid
+                    )
+                })?;
+                client = Some(name.to_string());
+            }
+        }
+        entries.push(Entry {
+            id: v.id,
+            start: v.start,
+            stop: v.stop,
+            description: v.description,
+            client,
+            project,
+            tags: v.tags.unwrap_or_default(),
+            billable: v.billable,
+        });
+    }
+    Ok(entries)
+}
+
+#[test]
+fn test_parse_json_entries() -> Result<()> {
+    assert_eq!(
+        parse_json_entries(
+            r#"[
+                {"id": 1, "workspace_id": 2, "start": "2021-03-08T09:00:00Z",
+                 "stop": "2021-03-08T10:30:00Z", "duration": 5400, "description": "Work",
+                 "client_name": "Client", "project_name": "Project", "tags": ["meetings"],
+                 "billable": true},
+                {"id": 3, "start": "2021-03-08T11:00:00+00:00", "stop": null,
+                 "duration": -1615194000}
+            ]"#
+            .as_bytes()
+        )?,
+        vec![
+            Entry {
+                id: Some(1),
+                start: Utc.ymd(2021, 3, 8).and_hms(9, 0, 0),
+                stop: Some(Utc.ymd(2021, 3, 8).and_hms(10, 30, 0)),
+                description: Some("Work".to_string()),
+                client: Some("Client".to_string()),
+                project: Some("Project".to_string()),
+                tags: vec!["meetings".to_string()],
+                billable: true,
+            },
+            Entry {
+                id: Some(3),
+                start: Utc.ymd(2021, 3, 8).and_hms(11, 0, 0),
+                stop: None,
+                description: None,
+                client: None,
+                project: None,
+                tags: Vec::new(),
+                billable: false,
+            }
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_parse_json_entries_stop_before_start() {
+    let err = parse_json_entries(
+        r#"[{"id": 1, "start": "2021-03-08T10:00:00Z", "stop": "2021-03-08T09:00:00Z"}]"#
+            .as_bytes(),
+    )
+    .unwrap_err();
+    assert_eq!(err.to_string(), "Time entry 1 stops before it starts");
+}
+
+#[test]
+fn test_parse_json_entries_v8() -> Result<()> {
+    let entries = parse_json_entries(
+        r#"{
+            "time_entries": [
+                {"id": 1, "wid": 2, "pid": 3, "start": "2021-03-08T09:00:00+00:00",
+                 "stop": "2021-03-08T10:00:00+00:00", "duration": 3600},
+                {"id": 2, "wid": 2, "pid": 4, "start": "2021-03-08T10:00:00+00:00",
+                 "stop": "2021-03-08T11:00:00+00:00", "duration": 3600}
+            ],
+            "projects": [
+                {"id": 3, "wid": 2, "cid": 5, "name": "Project"},
+                {"id": 4, "wid": 2, "name": "Internal"}
+            ],
+            "clients": [{"id": 5, "wid": 2, "name": "Client"}]
+        }"#
+        .as_bytes(),
+    )?;
+    let names: Vec<_> = entries
+        .iter()
+        .map(|v| (v.client.as_deref(), v.project.as_deref()))
+        .collect();
+    assert_eq!(
+        names,
+        vec![(Some("Client"), Some("Project")), (None, Some("Internal"))]
+    );
+    let err = parse_json_entries(
+        r#"[{"id": 1, "pid": 3, "start": "2021-03-08T09:00:00+00:00",
+             "stop": "2021-03-08T10:00:00+00:00"}]"#
+            .as_bytes(),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Time entry 1 has project id 3, which is not in the export's projects"
+    );
+    Ok(())
+}
+
+/// Row of a Toggl detailed report CSV export.  Dates and times are local.
+#[derive(Debug, Deserialize)]
+struct CsvTimeEntry {
+    #[serde(rename = "Client")]
+    client: String,
+    #[serde(rename = "Project")]
+    project: String,
+    #[serde(rename = "Description")]
+    description: String,
+    #[serde(rename = "Start date")]
+    start_date: NaiveDate,
+    #[serde(rename = "Start time")]
+    start_time: NaiveTime,
+    #[serde(rename = "End date")]
+    end_date: NaiveDate,
+    #[serde(rename = "End time")]
+    end_time: NaiveTime,
+    #[serde(rename = "Billable")]
+    billable: String,
+    #[serde(rename = "Tags", default)]
+    tags: String,
+}
+
+fn local_to_utc(date: NaiveDate, time: NaiveTime) -> Result<DateTime<Utc>> {
+    let datetime = date.and_time(time);
+    Local
+        .from_local_datetime(&datetime)
+        .single()
+        .map(|v| v.with_timezone(&Utc))
+        .ok_or_else(|| anyhow!("ambiguous or invalid local time: {}", datetime))
+}
+
+fn non_empty(value: String) -> Option<String> {
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+fn parse_csv_entries<R: Read>(reader: R) -> Result<Vec<Entry>> {
+    let mut csv_reader = csv::Reader::from_reader(reader);
+    let mut entries = Vec::new();
+    for (index, result) in csv_reader.deserialize().enumerate() {
+        let csv_entry: CsvTimeEntry = result?;
+        let start = local_to_utc(csv_entry.start_date, csv_entry.start_time)?;
+        let stop = local_to_utc(csv_entry.end_date, csv_entry.end_time)?;
+        if stop < start {
+            // Line 1 is the header
+            bail!("Time entry on line {} stops before it starts", index + 2);
+        }
+        entries.push(Entry {
+            id: None,
+            start,
+            stop: Some(stop),
+            description: non_empty(csv_entry.description),
+            client: non_empty(csv_entry.client),
+            project: non_empty(csv_entry.project),
+            billable: csv_entry.billable == "Yes",
+            tags: csv_entry
+                .tags
+                .split(',')
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(String::from)
+                .collect(),
+        });
+    }
+    Ok(entries)
+}
+
+#[test]
+fn test_parse_csv_entries() -> Result<()> {
+    assert_eq!(
+        parse_csv_entries(
+            "User,Email,Client,Project,Task,Description,Billable,Start date,Start time,\
+             End date,End time,Duration,Tags,Amount ()\n\
+             Me,me@example.com,Client,Project,,Work,Yes,2021-03-08,09:00:00,\
+             2021-03-08,10:30:00,01:30:00,\"support, meetings\",\n\
+             Me,me@example.com,,,,,No,2021-03-08,23:00:00,2021-03-09,01:00:00,02:00:00,,\n"
+                .as_bytes()
+        )?,
+        vec![
+            Entry {
+                id: None,
+                start: Local.ymd(2021, 3, 8).and_hms(9, 0, 0).with_timezone(&Utc),
+                stop: Some(Local.ymd(2021, 3, 8).and_hms(10, 30, 0).with_timezone(&Utc)),
+                description: Some("Work".to_string()),
+                client: Some("Client".to_string()),
+                project: Some("Project".to_string()),
+                tags: vec!["support".to_string(), "meetings".to_string()],
+                billable: true,
+            },
+            Entry {
+                id: None,
+                start: Local.ymd(2021, 3, 8).and_hms(23, 0, 0).with_timezone(&Utc),
+                stop: Some(Local.ymd(2021, 3, 9).and_hms(1, 0, 0).with_timezone(&Utc)),
+                description: None,
+                client: None,
+                project: None,
+                tags: Vec::new(),
+                billable: false,
+            }
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_parse_csv_entries_stop_before_start() {
+    let err = parse_csv_entries(
+        "Client,Project,Description,Billable,Start date,Start time,End date,End time\n\
+         Client,Project,Work,Yes,2021-03-08,09:00:00,2021-03-08,10:30:00\n\
+         Client,Project,Work,Yes,2021-03-09,09:00:00,2021-03-08,10:30:00\n"
+            .as_bytes(),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Time entry on line 3 stops before it starts"
+    );
+}
+
+/// Reads time entries from a local file instead of the Toggl API.  Files with
+/// a `.csv` extension are read as a Toggl detailed report CSV export, and any
+/// other files as JSON in the shape returned by the Toggl time entries API.
+#[derive(Clone, Debug)]
+pub struct FileTimeEntrySource {
+    path: PathBuf,
+}
+
+impl FileTimeEntrySource {
+    pub fn new<P: Into<PathBuf>>(path: P) -> FileTimeEntrySource {
+        FileTimeEntrySource { path: path.into() }
+    }
+
+    fn is_csv(&self) -> bool {
+        self.path
+            .extension()
+            .and_then(|v| v.to_str())
+            .map(|v| v.eq_ignore_ascii_case("csv"))
+            .unwrap_or(false)
+    }
+
+    fn load(&self) -> Result<Vec<Entry>> {
+        let file = File::open(&self.path).with_context(|| {
+            format!("could not open time entries file: {}", self.path.display())
+        })?;
+        let reader = BufReader::new(file);
+        let entries = if self.is_csv() {
+            parse_csv_entries(reader)
+        } else {
+            parse_json_entries(reader)
+        };
+        entries
+            .with_context(|| format!("could not parse time entries file: {}", self.path.display()))
+    }
+}
+
+impl TimeEntrySource for FileTimeEntrySource {
+    fn fetch_time_entries(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<Vec<Entry>> {
+        self.load()?.fetch_time_entries(start, end)
+    }
+}
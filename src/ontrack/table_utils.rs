@@ -1,9 +1,9 @@
 use chrono::Duration;
 use prettytable::{color, format::Alignment, Attr, Cell};
 
-fn duration_hours_cell_(duration: Duration, color: bool) -> Cell {
+pub fn format_duration_hours(duration: Duration) -> String {
     let seconds = duration.num_seconds();
-    let formatted = if seconds < 0 {
+    if seconds < 0 {
         format!(
             "-{}:{:02}",
             seconds.abs() / 3600,
@@ -11,8 +11,12 @@ fn duration_hours_cell_(duration: Duration, color: bool) -> Cell {
         )
     } else {
         format!("{}:{:02}", seconds / 3600, (seconds % 3600) / 60)
-    };
-    let mut cell = Cell::new_align(&formatted, Alignment::RIGHT);
+    }
+}
+
+fn duration_hours_cell_(duration: Duration, color: bool) -> Cell {
+    let seconds = duration.num_seconds();
+    let mut cell = Cell::new_align(&format_duration_hours(duration), Alignment::RIGHT);
     match (color, seconds) {
         (true, v) if v < 0 => cell.style(Attr::ForegroundColor(color::RED)),
         (true, v) if v > 0 => cell.style(Attr::ForegroundColor(color::GREEN)),
@@ -1,9 +1,11 @@
 use chrono::prelude::*;
 use chrono::Duration;
 use derive_more::{Add, Sub};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
 
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq)]
 #[serde(rename_all = "kebab-case", untagged, deny_unknown_fields)]
@@ -20,17 +22,95 @@ pub enum WorkDaysInput {
     DayHours(HashMap<WorkDayInput, f64>),
 }
 
+/// Time of day, as `HH:MM` or `HH:MM:SS`
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(try_from = "String")]
+pub struct TimeOfDayInput(pub NaiveTime);
+
+impl TryFrom<String> for TimeOfDayInput {
+    type Error = chrono::ParseError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        NaiveTime::parse_from_str(&value, "%H:%M")
+            .or_else(|_| NaiveTime::parse_from_str(&value, "%H:%M:%S"))
+            .map(TimeOfDayInput)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct WorkHoursInput {
+    pub from: TimeOfDayInput,
+    pub to: TimeOfDayInput,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RolloverInput {
+    Full,
+    None,
+    MaxHours(f64),
+    Window(usize),
+}
+
+/// Which time entries count towards a client or project's actual hours
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd)]
+#[serde(rename_all = "kebab-case")]
+pub enum CountInput {
+    Billable,
+    NonBillable,
+    All,
+}
+
+impl CountInput {
+    pub fn includes(self, billable: bool) -> bool {
+        match self {
+            CountInput::Billable => billable,
+            CountInput::NonBillable => !billable,
+            CountInput::All => true,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
-pub struct ProjectInput {
+pub struct TagInput {
     pub expected_hours: f64,
 }
 
+/// Budget for time entries without a client or project
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
-pub struct ClientInput {
+pub struct OverheadInput {
     pub expected_hours: f64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProjectInput {
+    pub expected_hours: Option<f64>,
+    pub expected_percent: Option<f64>,
+    pub min_hours: Option<f64>,
+    pub max_hours: Option<f64>,
+    pub work_days: Option<WorkDaysInput>,
+    pub count: Option<CountInput>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ClientInput {
+    pub expected_hours: Option<f64>,
+    pub min_hours: Option<f64>,
+    pub max_hours: Option<f64>,
+    pub work_days: Option<WorkDaysInput>,
+    pub rollover: Option<RolloverInput>,
+    pub count: Option<CountInput>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
     pub projects: Option<HashMap<String, ProjectInput>>,
+    pub tags: Option<HashMap<String, TagInput>>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -39,7 +119,32 @@ pub struct PeriodInput {
     pub start: NaiveDate,
     pub length: Option<i64>,
     pub work_days: Option<WorkDaysInput>,
+    pub work_hours: Option<WorkHoursInput>,
+    pub holiday_calendar: Option<String>,
+    pub clients: HashMap<String, ClientInput>,
+    pub tags: Option<HashMap<String, TagInput>>,
+    pub overhead: Option<OverheadInput>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", untagged, deny_unknown_fields)]
+pub enum HolidayInput {
+    Date(NaiveDate),
+    Range { from: NaiveDate, to: NaiveDate },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ScheduleInput {
+    pub start: NaiveDate,
+    pub end: Option<NaiveDate>,
+    pub length: Option<i64>,
+    pub work_days: Option<WorkDaysInput>,
+    pub work_hours: Option<WorkHoursInput>,
+    pub holiday_calendar: Option<String>,
     pub clients: HashMap<String, ClientInput>,
+    pub tags: Option<HashMap<String, TagInput>>,
+    pub overhead: Option<OverheadInput>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -47,6 +152,9 @@ pub struct PeriodInput {
 pub struct DefaultsInput {
     pub period_length: Option<i64>,
     pub work_days: Option<WorkDaysInput>,
+    pub work_hours: Option<WorkHoursInput>,
+    pub holiday_calendar: Option<String>,
+    pub rollover: Option<RolloverInput>,
 }
 
 impl Default for DefaultsInput {
@@ -54,6 +162,9 @@ impl Default for DefaultsInput {
         DefaultsInput {
             period_length: None,
             work_days: None,
+            work_hours: None,
+            holiday_calendar: None,
+            rollover: None,
         }
     }
 }
@@ -63,9 +174,65 @@ impl Default for DefaultsInput {
 pub struct Input {
     #[serde(default)]
     pub defaults: DefaultsInput,
+    #[serde(default)]
+    pub holidays: Vec<HolidayInput>,
+    #[serde(default)]
+    pub schedules: Vec<ScheduleInput>,
+    #[serde(default)]
     pub periods: Vec<PeriodInput>,
 }
 
+pub fn hours_duration(hours: f64) -> Duration {
+    Duration::seconds((hours * 3600.0).round() as i64)
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Entry {
+    pub id: Option<i64>,
+    pub start: DateTime<Utc>,
+    pub stop: Option<DateTime<Utc>>,
+    pub description: Option<String>,
+    pub client: Option<String>,
+    pub project: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub billable: bool,
+}
+
+#[cfg(test)]
+impl Entry {
+    /// A time entry from `start` until `stop` (or still running), with no id,
+    /// description or tags
+    pub fn for_test(
+        client: Option<&str>,
+        project: Option<&str>,
+        start: DateTime<Local>,
+        stop: Option<DateTime<Local>>,
+    ) -> Entry {
+        Entry {
+            id: None,
+            start: start.with_timezone(&Utc),
+            stop: stop.map(|v| v.with_timezone(&Utc)),
+            description: None,
+            client: client.map(String::from),
+            project: project.map(String::from),
+            tags: Vec::new(),
+            billable: false,
+        }
+    }
+}
+
+/// How unmatched time entries are grouped in the unmatched entries table
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct UnmatchedKey {
+    pub date: NaiveDate,
+    pub client: Option<String>,
+    pub project: Option<String>,
+    pub description: Option<String>,
+}
+
 #[derive(Add, Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Sub)]
 pub struct Durations {
     pub expected: Duration,
@@ -73,6 +240,8 @@ pub struct Durations {
     pub actual: Duration,
     pub today_actual: Duration,
     pub current_period_actual: Duration,
+    pub current_period_billable_actual: Duration,
+    pub current_period_non_billable_actual: Duration,
 }
 
 impl Durations {
@@ -83,12 +252,14 @@ impl Durations {
             actual: Duration::zero(),
             today_actual: Duration::zero(),
             current_period_actual: Duration::zero(),
+            current_period_billable_actual: Duration::zero(),
+            current_period_non_billable_actual: Duration::zero(),
         }
     }
 
     pub fn expected(expected_hours: f64, partial_percent: f64) -> Durations {
         let mut result = Durations::zero();
-        result.expected = Duration::seconds((expected_hours * 3600.0).round() as i64);
+        result.expected = hours_duration(expected_hours);
         result.partial_expected = Duration::seconds(i64::max(
             (expected_hours * 3600.0 * partial_percent).round() as i64,
             0,
@@ -121,25 +292,91 @@ impl Durations {
     }
 }
 
+/// Client, project or tag to track hours for.  A bucket with a tag and no
+/// client matches time entries with the tag for any client, and a bucket with
+/// neither is for time entries without a client.  A `no_project` bucket is for
+/// a client's time entries without a project, which are not part of the
+/// client's hours.
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct Bucket {
-    pub client: String,
+    pub client: Option<String>,
     pub project: Option<String>,
+    pub tag: Option<String>,
+    #[serde(default)]
+    pub no_project: bool,
+}
+
+impl Bucket {
+    pub fn unassigned() -> Bucket {
+        Bucket {
+            client: None,
+            project: None,
+            tag: None,
+            no_project: false,
+        }
+    }
+
+    pub fn no_project(client: &str) -> Bucket {
+        Bucket {
+            client: Some(client.to_string()),
+            project: None,
+            tag: None,
+            no_project: true,
+        }
+    }
+
+    /// Whether this bucket tracks all of a client's hours
+    pub fn is_client_total(&self) -> bool {
+        self.client.is_some() && self.project.is_none() && self.tag.is_none() && !self.no_project
+    }
+
+    /// Whether this bucket tracks time entries without a client
+    pub fn is_unassigned(&self) -> bool {
+        self.client.is_none() && self.project.is_none() && self.tag.is_none()
+    }
+
+    pub fn client_label(&self) -> &str {
+        match (&self.client, &self.tag) {
+            (Some(client), _) => client,
+            (None, Some(_)) => "",
+            (None, None) => "(no client)",
+        }
+    }
+
+    pub fn project_label(&self) -> String {
+        match (&self.client, &self.project, &self.tag) {
+            (_, Some(project), _) => project.clone(),
+            (_, None, Some(tag)) => format!("#{}", tag),
+            (Some(_), None, None) if !self.no_project => String::new(),
+            (_, None, None) => "(no project)".to_string(),
+        }
+    }
+}
+
+/// The bucket for all of a client's hours
+#[cfg(test)]
+pub fn client_bucket(client: &str) -> Bucket {
+    Bucket {
+        client: Some(client.to_string()),
+        project: None,
+        tag: None,
+        no_project: false,
+    }
 }
 
 impl Ord for Bucket {
+    /// Buckets without a client sort last, and within a client: projects, then
+    /// tags, then time entries without a project, then the client itself
     fn cmp(&self, other: &Self) -> Ordering {
-        match (
-            self.client.cmp(&other.client),
-            &self.project,
-            &other.project,
-        ) {
-            (Ordering::Equal, Some(_), None) => Ordering::Less,
-            (Ordering::Equal, None, Some(_)) => Ordering::Greater,
-            (Ordering::Equal, self_project, other_project) => self_project.cmp(&other_project),
-            (ordering, _, _) => ordering,
-        }
+        (self.client.is_none(), &self.client)
+            .cmp(&(other.client.is_none(), &other.client))
+            .then_with(|| {
+                (self.project.is_none(), &self.project)
+                    .cmp(&(other.project.is_none(), &other.project))
+            })
+            .then_with(|| (self.tag.is_none(), &self.tag).cmp(&(other.tag.is_none(), &other.tag)))
+            .then_with(|| other.no_project.cmp(&self.no_project))
     }
 }
 
@@ -149,6 +386,27 @@ impl PartialOrd for Bucket {
     }
 }
 
+/// How much of the over/under hours from previous periods is carried over into
+/// a period
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Rollover {
+    Full,
+    None,
+    MaxHours(Duration),
+    Window(usize),
+}
+
+impl From<RolloverInput> for Rollover {
+    fn from(input: RolloverInput) -> Rollover {
+        match input {
+            RolloverInput::Full => Rollover::Full,
+            RolloverInput::None => Rollover::None,
+            RolloverInput::MaxHours(hours) => Rollover::MaxHours(hours_duration(hours)),
+            RolloverInput::Window(periods) => Rollover::Window(periods),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct PeriodBucketDurations {
     pub period_start: Date<Local>,
@@ -156,10 +414,95 @@ pub struct PeriodBucketDurations {
     pub period_length: i64,
     pub last_work_day_offset: i64,
     pub durations: Durations,
+    /// Upper bound of the expected hours
+    pub max_expected: MaxExpected,
+    pub day_expected: Vec<Duration>,
+    pub day_actual: Vec<Duration>,
+    pub rollover: Rollover,
+    pub count: CountInput,
+}
+
+impl PeriodBucketDurations {
+    /// Over/under hours at the end of this period, given the over/under hours
+    /// carried into it.  For a range, hours within the range are not over or
+    /// under.
+    pub fn balance(&self, carried: Duration) -> Duration {
+        let actual = carried + self.durations.actual;
+        if actual < self.durations.expected {
+            return actual - self.durations.expected;
+        }
+        match self.max_expected.upper_bound(self.durations.expected) {
+            Some(max_expected) if actual > max_expected => actual - max_expected,
+            _ => Duration::zero(),
+        }
+    }
+}
+
+/// Upper bound of a bucket's expected hours
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum MaxExpected {
+    /// The expected hours are exact
+    Exact,
+    /// The expected hours are a range (from the expected hours), with no upper
+    /// bound if `None`
+    Range(Option<Duration>),
+}
+
+impl MaxExpected {
+    pub fn is_range(self) -> bool {
+        matches!(self, MaxExpected::Range(_))
+    }
+
+    /// Upper bound given the expected hours, or `None` if there is none
+    pub fn upper_bound(self, expected: Duration) -> Option<Duration> {
+        match self {
+            MaxExpected::Exact => Some(expected),
+            MaxExpected::Range(max_expected) => max_expected,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RangeStatus {
+    Under,
+    InRange,
+    Over,
+}
+
+impl fmt::Display for RangeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            RangeStatus::Under => "under",
+            RangeStatus::InRange => "in range",
+            RangeStatus::Over => "over",
+        })
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct TotalDurations {
     pub end_work_date: Date<Local>,
     pub durations: Durations,
+    /// Whether the hours are within the range, for buckets with an hours range
+    pub status: Option<RangeStatus>,
+    /// How long the bucket's timer has been running, if it is
+    pub running: Option<Duration>,
+    /// Whether any hours are expected for the bucket.  Unassigned time without
+    /// an overhead budget is tracked, but not expected.
+    pub budgeted: bool,
+}
+
+impl TotalDurations {
+    pub fn whole_days_until_end_work(&self, now: DateTime<Local>) -> i64 {
+        (self.end_work_date - (now.date() + Duration::days(1))).num_days()
+    }
+
+    pub fn daily_average_remaining(&self, now: DateTime<Local>) -> Option<Duration> {
+        if !self.budgeted {
+            return None;
+        }
+        self.durations
+            .daily_average_remaining(self.whole_days_until_end_work(now))
+    }
 }
@@ -0,0 +1,641 @@
+use crate::ontrack::processor::*;
+use crate::ontrack::types::*;
+use anyhow::*;
+use chrono::prelude::*;
+use chrono::Duration;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use yaml_rust::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust::scanner::Marker;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A problem found in the input file, with the line (starting at 1) it is on,
+/// if known
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InputProblem {
+    pub severity: Severity,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl InputProblem {
+    pub fn describe(&self, input_file: &str) -> String {
+        match self.line {
+            Some(line) => format!("{}:{}: {}", input_file, line, self.message),
+            None => format!("{}: {}", input_file, self.message),
+        }
+    }
+}
+
+/// A step in the path from the root of a YAML document to one of its nodes
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum PathStep {
+    Key(String),
+    Index(usize),
+    /// A mapping key that is not a scalar
+    ComplexKey,
+}
+
+/// Extend a path with mapping keys
+fn key_path(path: &[PathStep], keys: &[&str]) -> Vec<PathStep> {
+    let mut result = path.to_vec();
+    result.extend(keys.iter().map(|v| PathStep::Key(v.to_string())));
+    result
+}
+
+/// Path of the period or schedule at `index` in the top-level `section`
+/// (`periods` or `schedules`)
+fn period_path(section: &str, index: usize) -> Vec<PathStep> {
+    vec![PathStep::Key(section.to_string()), PathStep::Index(index)]
+}
+
+enum Frame {
+    Sequence(usize),
+    Mapping {
+        key: Option<PathStep>,
+        in_value: bool,
+    },
+}
+
+/// The lines (starting at 1) of the nodes in a YAML document by their path,
+/// where a mapping value is on its key's line
+#[derive(Default)]
+struct NodeLines {
+    lines: HashMap<Vec<PathStep>, usize>,
+    frames: Vec<Frame>,
+}
+
+impl NodeLines {
+    fn parse(text: &str) -> Result<NodeLines> {
+        let mut node_lines = NodeLines::default();
+        Parser::new(text.chars()).load(&mut node_lines, false)?;
+        Ok(node_lines)
+    }
+
+    fn line(&self, path: &[PathStep]) -> Option<usize> {
+        self.lines.get(path).copied()
+    }
+
+    /// Record the line of a node that starts at `mark`, unless it is a mapping
+    /// value (whose key's line has already been recorded)
+    fn begin_node(&mut self, scalar: Option<&str>, mark: Marker) {
+        match self.frames.last_mut() {
+            Some(Frame::Mapping {
+                key: key @ None, ..
+            }) => {
+                *key = Some(match scalar {
+                    Some(scalar) => PathStep::Key(scalar.to_string()),
+                    None => PathStep::ComplexKey,
+                });
+            }
+            Some(Frame::Mapping { .. }) => return,
+            _ => (),
+        }
+        let path = self
+            .frames
+            .iter()
+            .filter_map(|frame| match frame {
+                Frame::Sequence(index) => Some(PathStep::Index(*index)),
+                Frame::Mapping { key, .. } => key.clone(),
+            })
+            .collect();
+        self.lines.entry(path).or_insert_with(|| mark.line());
+    }
+
+    /// Move past a node that has ended
+    fn end_node(&mut self) {
+        match self.frames.last_mut() {
+            Some(Frame::Sequence(index)) => *index += 1,
+            Some(Frame::Mapping { key, in_value }) => {
+                if *in_value {
+                    *key = None;
+                }
+                *in_value = !*in_value;
+            }
+            None => (),
+        }
+    }
+}
+
+impl MarkedEventReceiver for NodeLines {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        match event {
+            Event::Scalar(value, ..) => {
+                self.begin_node(Some(&value), mark);
+                self.end_node();
+            }
+            Event::Alias(_) => {
+                self.begin_node(None, mark);
+                self.end_node();
+            }
+            Event::SequenceStart(_) => {
+                self.begin_node(None, mark);
+                self.frames.push(Frame::Sequence(0));
+            }
+            Event::MappingStart(_) => {
+                self.begin_node(None, mark);
+                self.frames.push(Frame::Mapping {
+                    key: None,
+                    in_value: false,
+                });
+            }
+            Event::SequenceEnd | Event::MappingEnd => {
+                self.frames.pop();
+                self.end_node();
+            }
+            _ => (),
+        }
+    }
+}
+
+#[test]
+fn test_node_lines() -> Result<()> {
+    let node_lines = NodeLines::parse(
+        "
+# start: 2021-03-01
+periods:
+- start: 2021-03-07
+  clients:
+    Client 10:
+      expected-hours: 10
+    \"Client 1\": {expected-hours: 5}
+- start: 2021-03-14
+  clients:
+    'Client 1':
+      projects: {First: {expected-hours: 2}}
+",
+    )?;
+    let line =
+        |keys: &[&str], index| node_lines.line(&key_path(&period_path("periods", index), keys));
+    assert_eq!(line(&[], 0), Some(4));
+    assert_eq!(line(&["start"], 0), Some(4));
+    assert_eq!(line(&["clients", "Client 10"], 0), Some(6));
+    assert_eq!(line(&["clients", "Client 1"], 0), Some(8));
+    assert_eq!(line(&[], 1), Some(9));
+    assert_eq!(
+        line(&["clients", "Client 1", "projects", "First"], 1),
+        Some(12)
+    );
+    assert_eq!(line(&["clients", "Client 10"], 1), None);
+    Ok(())
+}
+
+struct Validator {
+    node_lines: NodeLines,
+    problems: Vec<InputProblem>,
+}
+
+impl Validator {
+    fn line(&self, path: &[PathStep]) -> Option<usize> {
+        self.node_lines.line(path)
+    }
+
+    /// Find the path of an expanded period's schedule or period, and describe
+    /// the schedule it was generated from, if any
+    fn source_path(
+        &self,
+        input: &Input,
+        source: PeriodSource,
+        period: &PeriodInput,
+    ) -> (Vec<PathStep>, String) {
+        match source {
+            PeriodSource::Schedule(index) => {
+                let schedule_start = input.schedules[index].start;
+                let origin = if schedule_start == period.start {
+                    String::new()
+                } else {
+                    format!(" (from schedule starting {})", schedule_start)
+                };
+                (period_path("schedules", index), origin)
+            }
+            PeriodSource::Period(index) => (period_path("periods", index), String::new()),
+        }
+    }
+
+    fn add(&mut self, severity: Severity, line: Option<usize>, message: String) {
+        self.problems.push(InputProblem {
+            severity,
+            line,
+            message,
+        });
+    }
+
+    fn error(&mut self, line: Option<usize>, message: String) {
+        self.add(Severity::Error, line, message);
+    }
+
+    fn warning(&mut self, line: Option<usize>, message: String) {
+        self.add(Severity::Warning, line, message);
+    }
+
+    fn check_length(&mut self, line: Option<usize>, what: &str, length: Option<i64>) {
+        if let Some(length) = length {
+            if length <= 0 {
+                self.error(line, format!("{} must have a positive length", what));
+            }
+        }
+    }
+
+    fn check_work_hours(
+        &mut self,
+        parent_path: &[PathStep],
+        what: &str,
+        work_hours: Option<&WorkHoursInput>,
+    ) {
+        if let Some(WorkHoursInput { from, to }) = work_hours {
+            if to.0 <= from.0 {
+                let line = self
+                    .line(&key_path(parent_path, &["work-hours"]))
+                    .or_else(|| self.line(parent_path));
+                self.error(
+                    line,
+                    format!("{}: work hours must end after they start", what),
+                );
+            }
+        }
+    }
+
+    /// Check work days, and that any hours they allocate fit in
+    /// `expected_hours` (unless it is `None`)
+    fn check_work_days(
+        &mut self,
+        line: Option<usize>,
+        what: &str,
+        period: &PeriodInput,
+        period_length: i64,
+        work_days_input: &WorkDaysInput,
+        expected_hours: Option<f64>,
+    ) {
+        let offsets: Vec<i64> = match work_days_input {
+            WorkDaysInput::FromToWeekdays { .. } => Vec::new(),
+            WorkDaysInput::FromToOffsets { from, to } => vec![*from, *to],
+            WorkDaysInput::DayHours(day_hours) => day_hours
+                .keys()
+                .filter_map(|v| match v {
+                    WorkDayInput::Offset(offset) => Some(*offset),
+                    WorkDayInput::Weekday(_) => None,
+                })
+                .collect(),
+        };
+        for offset in offsets {
+            if offset < 0 || offset >= period_length {
+                self.error(
+                    line,
+                    format!(
+                        "{}: work day offset {} is outside the period length of {} days",
+                        what, offset, period_length
+                    ),
+                );
+            }
+        }
+        if let WorkDaysInput::FromToOffsets { from, to } = work_days_input {
+            if to < from {
+                self.error(
+                    line,
+                    format!(
+                        "{}: work day offsets from {} to {} end before they start",
+                        what, from, to
+                    ),
+                );
+            }
+        }
+        if let (WorkDaysInput::DayHours(_), Some(expected_hours)) =
+            (work_days_input, expected_hours)
+        {
+            let period_start = Local.from_local_date(&period.start).unwrap();
+            match preallocate_hours(
+                period_start,
+                period_length,
+                work_days_input,
+                &HashSet::new(),
+            ) {
+                Ok((_, total)) if total > expected_hours => self.error(
+                    line,
+                    format!(
+                        "{}: work day hours add up to {}, more than the expected {} hours",
+                        what, total, expected_hours
+                    ),
+                ),
+                Ok(_) => (),
+                Err(error) => self.error(line, format!("{}: {}", what, error)),
+            }
+        }
+    }
+
+    fn check_client(
+        &mut self,
+        period_path: &[PathStep],
+        kind: &str,
+        period: &PeriodInput,
+        period_length: i64,
+        client_name: &str,
+        client_input: &ClientInput,
+    ) -> Option<f64> {
+        let client_path = key_path(period_path, &["clients", client_name]);
+        let line = self.line(&client_path);
+        let what = format!(
+            "Client {} in {} starting {}",
+            client_name,
+            kind.to_lowercase(),
+            period.start
+        );
+        let (expected_hours, max_expected) = match resolve_hours_range(
+            client_input.expected_hours,
+            client_input.min_hours,
+            client_input.max_hours,
+        ) {
+            Ok(result) => result,
+            Err(error) => {
+                self.error(line, format!("{}: {}", what, error));
+                return None;
+            }
+        };
+        if let Some(work_days_input) = &client_input.work_days {
+            self.check_work_days(
+                line,
+                &what,
+                period,
+                period_length,
+                work_days_input,
+                Some(expected_hours),
+            );
+        }
+        let mut projects_expected = Duration::zero();
+        let mut projects: Vec<_> = client_input.projects.iter().flatten().collect();
+        projects.sort_by_key(|(name, _)| *name);
+        for (project_name, project_input) in projects {
+            let project_line = self.line(&key_path(&client_path, &["projects", project_name]));
+            let project_what = format!(
+                "Project {} / {} in {} starting {}",
+                client_name,
+                project_name,
+                kind.to_lowercase(),
+                period.start
+            );
+            match resolve_project_hours_range(project_input, expected_hours) {
+                Ok((project_expected_hours, _)) => {
+                    projects_expected = projects_expected + hours_duration(project_expected_hours);
+                    if let Some(work_days_input) = &project_input.work_days {
+                        self.check_work_days(
+                            project_line,
+                            &project_what,
+                            period,
+                            period_length,
+                            work_days_input,
+                            Some(project_expected_hours),
+                        );
+                    }
+                }
+                Err(error) => self.error(project_line, format!("{}: {}", project_what, error)),
+            }
+        }
+        let client_expected = max_expected.upper_bound(hours_duration(expected_hours));
+        if client_expected.map_or(false, |v| projects_expected > v) {
+            self.error(
+                line,
+                format!(
+                    "{}: project hours add up to more than the client's hours",
+                    what
+                ),
+            );
+        }
+        Some(expected_hours)
+    }
+
+    /// Check a period, or the first period generated from a schedule.  `kind`
+    /// is `Period` or `Schedule`, and `path` is where it is in the input.
+    fn check_period(
+        &mut self,
+        path: &[PathStep],
+        kind: &str,
+        defaults_input: &DefaultsInput,
+        period: &PeriodInput,
+    ) {
+        let line = self.line(path);
+        let what = format!("{} starting {}", kind, period.start);
+        self.check_work_hours(path, &what, period.work_hours.as_ref());
+        let period_length = period.length.unwrap_or_else(|| {
+            defaults_input
+                .period_length
+                .unwrap_or(DEFAULT_PERIOD_LENGTH)
+        });
+        // Only clients without work days of their own use the period's, so
+        // if there are none, the period's work day hours are not checked
+        let mut shared_expected_hours = None;
+        let mut clients: Vec<_> = period.clients.iter().collect();
+        clients.sort_by_key(|(name, _)| *name);
+        for (client_name, client_input) in clients {
+            let expected_hours =
+                self.check_client(path, kind, period, period_length, client_name, client_input);
+            if client_input.work_days.is_none() {
+                *shared_expected_hours.get_or_insert(0.0) += expected_hours.unwrap_or(0.0);
+            }
+        }
+        let work_days_input = period.work_days.as_ref().unwrap_or_else(|| {
+            defaults_input
+                .work_days
+                .as_ref()
+                .unwrap_or(&DEFAULT_WORK_DAYS)
+        });
+        self.check_work_days(
+            line,
+            &what,
+            period,
+            period_length,
+            work_days_input,
+            shared_expected_hours,
+        );
+    }
+}
+
+/// Check the input for problems that its YAML structure does not prevent.
+/// `text` is the input file's contents, used to find line numbers.
+pub fn validate_input(today: NaiveDate, input: &Input, text: &str) -> Result<Vec<InputProblem>> {
+    let mut validator = Validator {
+        node_lines: NodeLines::parse(text).context("Could not find line numbers in input")?,
+        problems: Vec::new(),
+    };
+    let defaults_path = key_path(&[], &["defaults"]);
+    let defaults_line = validator.line(&key_path(&defaults_path, &["period-length"]));
+    validator.check_length(
+        defaults_line,
+        "Default period length",
+        input.defaults.period_length,
+    );
+    for (index, schedule_input) in input.schedules.iter().enumerate() {
+        let line = validator.line(&period_path("schedules", index));
+        let what = format!("Schedule starting {}", schedule_input.start);
+        validator.check_length(line, &what, schedule_input.length);
+    }
+    for (index, period_input) in input.periods.iter().enumerate() {
+        let line = validator.line(&period_path("periods", index));
+        let what = format!("Period starting {}", period_input.start);
+        validator.check_length(line, &what, period_input.length);
+    }
+    if !validator.problems.is_empty() {
+        return Ok(validator.problems);
+    }
+    validator.check_work_hours(
+        &defaults_path,
+        "Defaults",
+        input.defaults.work_hours.as_ref(),
+    );
+    let periods = expand_period_sources(today, input)?;
+    let period_end = |period: &PeriodInput| {
+        period.start
+            + Duration::days(period.length.unwrap_or_else(|| {
+                input
+                    .defaults
+                    .period_length
+                    .unwrap_or(DEFAULT_PERIOD_LENGTH)
+            }))
+    };
+    for pair in periods.windows(2) {
+        let ((_, previous), (next_source, next)) = (&pair[0], &pair[1]);
+        let (path, origin) = validator.source_path(input, *next_source, next);
+        let line = validator.line(&path);
+        let previous_end = period_end(previous);
+        match previous_end.cmp(&next.start) {
+            Ordering::Greater => validator.error(
+                line,
+                format!(
+                    "Period starting {}{} overlaps period starting {}",
+                    next.start, origin, previous.start
+                ),
+            ),
+            Ordering::Less => validator.warning(
+                line,
+                format!(
+                    "Gap from {} until period starting {}{}",
+                    previous_end, next.start, origin
+                ),
+            ),
+            Ordering::Equal => (),
+        }
+    }
+    // Each schedule is checked once, rather than every period it generates
+    for (index, schedule_input) in input.schedules.iter().enumerate() {
+        let period = schedule_period(schedule_input, schedule_input.start);
+        validator.check_period(
+            &period_path("schedules", index),
+            "Schedule",
+            &input.defaults,
+            &period,
+        );
+    }
+    for (index, period_input) in input.periods.iter().enumerate() {
+        validator.check_period(
+            &period_path("periods", index),
+            "Period",
+            &input.defaults,
+            period_input,
+        );
+    }
+    Ok(validator.problems)
+}
+
+#[test]
+fn test_validate_input() -> Result<()> {
+    let text = "
+periods:
+- start: 2021-03-07
+  clients:
+    Client:
+      expected-hours: 10
+      projects:
+        First:
+          expected-hours: 8
+        Second:
+          expected-hours: 4
+- start: 2021-03-12
+  work-days:
+    7: 0
+  clients:
+    Client:
+      expected-hours: 2
+      work-days:
+        mon: 4
+- start: 2021-03-28
+  clients:
+    Client:
+      expected-hours: 10
+- start: 2021-04-04
+  work-days:
+    mon: 8
+  clients:
+    Client:
+      expected-hours: 2
+      work-days:
+        tue: 2
+    Other:
+      expected-hours: 4
+      work-days: {from: 3, to: 1}
+";
+    let input: Input = serde_yaml::from_str(text)?;
+    let problems = validate_input(NaiveDate::from_ymd(2021, 3, 28), &input, text)?;
+    let described: Vec<_> = problems.iter().map(|v| v.describe("input.yaml")).collect();
+    assert_eq!(
+        described,
+        vec![
+            "input.yaml:12: Period starting 2021-03-12 overlaps period starting 2021-03-07",
+            "input.yaml:20: Gap from 2021-03-19 until period starting 2021-03-28",
+            "input.yaml:5: Client Client in period starting 2021-03-07: \
+             project hours add up to more than the client's hours",
+            "input.yaml:16: Client Client in period starting 2021-03-12: \
+             work day hours add up to 4, more than the expected 2 hours",
+            "input.yaml:12: Period starting 2021-03-12: \
+             work day offset 7 is outside the period length of 7 days",
+            // Every client has its own work days, so the period's are not
+            // checked against their hours
+            "input.yaml:32: Client Other in period starting 2021-04-04: \
+             work day offsets from 3 to 1 end before they start",
+        ]
+    );
+    assert_eq!(problems[1].severity, Severity::Warning);
+    Ok(())
+}
+
+#[test]
+fn test_validate_input_schedules() -> Result<()> {
+    let text = "
+defaults:
+  period-length: 7
+  work-hours: {from: '09:00', to: '09:00'}
+schedules:
+- start: 2021-03-07
+  work-hours:
+    from: '17:00'
+    to: '09:00'
+  clients:
+    Client:
+      expected-hours: 2
+      work-days:
+        mon: 4
+periods:
+- start: 2021-03-24
+  clients:
+    Other:
+      expected-hours: 10
+";
+    let input: Input = serde_yaml::from_str(text)?;
+    let problems = validate_input(NaiveDate::from_ymd(2021, 3, 28), &input, text)?;
+    let described: Vec<_> = problems.iter().map(|v| v.describe("input.yaml")).collect();
+    assert_eq!(
+        described,
+        vec![
+            "input.yaml:4: Defaults: work hours must end after they start",
+            "input.yaml:16: Period starting 2021-03-24 overlaps period starting 2021-03-21",
+            "input.yaml:6: Period starting 2021-03-28 (from schedule starting 2021-03-07) \
+             overlaps period starting 2021-03-24",
+            "input.yaml:7: Schedule starting 2021-03-07: work hours must end after they start",
+            "input.yaml:11: Client Client in schedule starting 2021-03-07: \
+             work day hours add up to 4, more than the expected 2 hours",
+        ]
+    );
+    Ok(())
+}